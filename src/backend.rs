@@ -0,0 +1,312 @@
+use crate::{
+    configs::{Backend, ModelConfig},
+    llama_cpp::LlamaCppBackend,
+    llama_server::LlamaServerBackend,
+    openai::OpenAiBackend,
+    templates::ChatTemplate,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, io,
+    io::{BufRead, Read},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// anything capable of turning a prompt into a stream of generated text
+pub trait InferenceBackend {
+    /// starts generating a reply to the given prompt, the timeout is how long the whole generation may take
+    fn generate(
+        &mut self,
+        model: &Path,
+        config: &ModelConfig,
+        prompt: &Prompt,
+        timeout: Duration,
+    ) -> io::Result<Generation>;
+}
+
+/// what a backend is asked to reply to
+pub struct Prompt {
+    // the exchanges laid out with the model's chat template, for backends that take raw text
+    pub text: String,
+    // the same exchanges as role/content pairs, for backends that apply their own chat template
+    pub messages: Vec<Message>,
+}
+
+impl Prompt {
+    /// the system prompt if there is one, the past exchanges as input and output pairs, then the new input
+    pub fn new<'a>(
+        template: &ChatTemplate,
+        system_prompt: &str,
+        exchanges: impl IntoIterator<Item = (&'a str, &'a str)>,
+        input: &str,
+    ) -> Self {
+        let mut messages = Vec::new();
+        if !system_prompt.is_empty() {
+            messages.push(Message {
+                role: Role::System,
+                content: system_prompt.to_string(),
+            });
+        }
+        exchanges.into_iter().for_each(|(input, output)| {
+            messages.push(Message {
+                role: Role::User,
+                content: input.to_string(),
+            });
+            messages.push(Message {
+                role: Role::Assistant,
+                content: output.to_string(),
+            });
+        });
+        messages.push(Message {
+            role: Role::User,
+            content: input.to_string(),
+        });
+        Self {
+            text: template.render(&messages),
+            messages,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+}
+
+/// the source of a generation's events, read from a background thread
+pub trait TextStream: Send {
+    /// called before reading starts, for streams that also report events from threads of their own
+    fn attach(&mut self, _events: Sender<GenerationEvent>) {}
+
+    /// blocks until something happens, errors end the generation as a failure
+    fn next_event(&mut self) -> io::Result<GenerationEvent>;
+}
+
+/// anything that has to be shut down for a generation to stop early, i.e. a process
+pub trait Interrupt: Send {
+    fn interrupt(self: Box<Self>);
+}
+
+/// set once a generation has been stopped, for streams that can't be shut down from outside and check it instead
+#[derive(Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+    /// an error once the flag is set, which ends the stream's thread
+    pub fn check(&self) -> io::Result<()> {
+        if self.is_set() {
+            return Err(io::Error::other("the generation was stopped"));
+        }
+        Ok(())
+    }
+}
+
+impl Interrupt for CancelFlag {
+    fn interrupt(self: Box<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// reads from a socket that has a read timeout, waiting past each timeout until the flag is set
+/// so that a reader blocked on a silent server still notices the generation being stopped
+pub struct CancellableReader<R> {
+    inner: R,
+    cancel: CancelFlag,
+}
+
+impl<R> CancellableReader<R> {
+    pub fn new(inner: R, cancel: CancelFlag) -> Self {
+        Self { inner, cancel }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            self.cancel.check()?;
+            match self.inner.read(buffer) {
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) => {}
+                result => return result,
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum GenerationEvent {
+    Text(String),
+    // what the backend is busy with before any text comes out, i.e. loading the model
+    Status(String),
+    Timings(Timings),
+    // something went wrong that the generation recovered from
+    Warning(String),
+    Finished(StopReason),
+    Failed(String),
+}
+
+/// why a generation ended
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopReason {
+    // the model ended its reply on its own
+    Eos,
+    // the reply reached the number of tokens to predict
+    TokenLimit,
+    // the generation took longer than the application's timeout
+    Timeout,
+    UserStop,
+    Error,
+}
+
+impl StopReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eos => "end of sequence",
+            Self::TokenLimit => "token limit reached",
+            Self::Timeout => "timed out",
+            Self::UserStop => "stopped by the user",
+            Self::Error => "failed",
+        }
+    }
+}
+
+/// the speed at which the prompt was processed and the reply generated
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timings {
+    pub prompt_tokens: u32,
+    pub prompt_tokens_per_second: f64,
+    pub generated_tokens: u32,
+    pub generated_tokens_per_second: f64,
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "prompt: {} tokens at {:.2} t/s, reply: {} tokens at {:.2} t/s",
+            self.prompt_tokens,
+            self.prompt_tokens_per_second,
+            self.generated_tokens,
+            self.generated_tokens_per_second
+        )
+    }
+}
+
+/// a generation in progress, its text stream is read on a separate thread so that polling it never blocks
+pub struct Generation {
+    receiver: Receiver<GenerationEvent>,
+    interrupt: Option<Box<dyn Interrupt>>,
+}
+
+impl Generation {
+    pub fn spawn(mut stream: Box<dyn TextStream>, interrupt: Option<Box<dyn Interrupt>>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        stream.attach(sender.clone());
+        thread::spawn(move || loop {
+            let event = stream
+                .next_event()
+                .unwrap_or_else(|error| GenerationEvent::Failed(error.to_string()));
+            let last = matches!(
+                event,
+                GenerationEvent::Finished(_) | GenerationEvent::Failed(_)
+            );
+            // the receiving end is gone once the generation has been stopped
+            if sender.send(event).is_err() || last {
+                break;
+            }
+        });
+        Self {
+            receiver,
+            interrupt,
+        }
+    }
+    /// returns the next event if one is available, without blocking
+    pub fn try_next(&self) -> Option<GenerationEvent> {
+        match self.receiver.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(GenerationEvent::Failed(
+                "the generation thread stopped unexpectedly".to_string(),
+            )),
+        }
+    }
+    /// waits for the next event, none if nothing came in time
+    pub fn wait_next(&self, timeout: Duration) -> Option<GenerationEvent> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(GenerationEvent::Failed(
+                "the generation thread stopped unexpectedly".to_string(),
+            )),
+        }
+    }
+    /// stops the generation early
+    pub fn stop(&mut self) {
+        if let Some(interrupt) = self.interrupt.take() {
+            interrupt.interrupt();
+        }
+    }
+}
+
+impl Drop for Generation {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// creates the backend selected in a model's configuration
+pub fn from_config(config: &ModelConfig) -> Box<dyn InferenceBackend> {
+    match config.backend {
+        Backend::LlamaCpp => Box::<LlamaCppBackend>::default(),
+        Backend::LlamaServer => Box::<LlamaServerBackend>::default(),
+        Backend::OpenAi => Box::<OpenAiBackend>::default(),
+    }
+}
+
+/// returns the payload of the next 'data:' line of a server-sent events stream, or None once it has ended
+pub fn next_sse_data<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if let Some(data) = line.trim_end().strip_prefix("data:") {
+            return Ok(Some(data.trim_start().to_string()));
+        }
+    }
+}
+
+pub fn to_io_error(error: ureq::Error) -> io::Error {
+    match error {
+        ureq::Error::Status(code, response) => io::Error::other(format!(
+            "the server answered with {} => {}",
+            code,
+            response.into_string().unwrap_or_default()
+        )),
+        ureq::Error::Transport(error) => io::Error::other(error.to_string()),
+    }
+}
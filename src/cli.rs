@@ -0,0 +1,182 @@
+use crate::paths::Paths;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage: sulmo [OPTIONS]
+       sulmo ask [OPTIONS] [PROMPT]...
+
+Commands:
+  ask                  answers PROMPT, or what is read from stdin, streams the reply to stdout and exits
+
+Options:
+  --model NAME         opens the chat of the first model whose file name contains NAME
+  --models-dir DIR     where models are looked for
+  --config-dir DIR     where config files are kept
+  --data-dir DIR       where sessions, exports and imports are kept, and models, configs and llama.cpp unless set otherwise
+  --llama-bin FILE     llama.cpp's 'main' executable, its server is looked for next to it
+  -V, --version        prints the version
+  -h, --help           prints this message
+
+Without any directory set, the working directory is used if it holds ./configs, ./models or ./llama-cpp,
+the XDG base directories ($XDG_CONFIG_HOME/sulmo and $XDG_DATA_HOME/sulmo) otherwise.";
+
+/// what Sulmo was asked to do from the command line
+#[derive(Debug, Default, PartialEq)]
+pub enum Command {
+    // the terminal user interface
+    #[default]
+    Run,
+    // a single prompt answered on stdout, see headless.rs
+    Ask,
+    Version,
+    Help,
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Command,
+    pub model: Option<String>,
+    // the words following 'ask' that aren't options
+    pub prompt: Vec<String>,
+    models_dir: Option<PathBuf>,
+    config_dir: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    llama_bin: Option<PathBuf>,
+}
+
+impl Args {
+    /// reads the arguments, without the executable's name, both '--option value' and '--option=value' are understood
+    pub fn parse<I: Iterator<Item = String>>(mut arguments: I) -> Result<Self, String> {
+        let mut args = Self::default();
+        while let Some(argument) = arguments.next() {
+            let (option, inline_value) = match argument.split_once('=') {
+                Some((option, value)) if option.starts_with("--") => {
+                    (option.to_string(), Some(value.to_string()))
+                }
+                _ => (argument.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| arguments.next())
+                    .ok_or(format!("{} expects a value", option))
+            };
+            match option.as_str() {
+                "--model" => args.model = Some(value()?),
+                "--models-dir" => args.models_dir = Some(value()?.into()),
+                "--config-dir" => args.config_dir = Some(value()?.into()),
+                "--data-dir" => args.data_dir = Some(value()?.into()),
+                "--llama-bin" => args.llama_bin = Some(value()?.into()),
+                "-V" | "--version" => args.command = Command::Version,
+                "-h" | "--help" => args.command = Command::Help,
+                "ask" if args.command == Command::Run => args.command = Command::Ask,
+                // everything after '--' is part of the prompt, even if it looks like an option
+                "--" if args.command == Command::Ask => args.prompt.extend(arguments.by_ref()),
+                _ if args.command == Command::Ask && !argument.starts_with('-') => {
+                    args.prompt.push(argument)
+                }
+                _ => return Err(format!("unknown argument '{}'", argument)),
+            }
+        }
+        Ok(args)
+    }
+    /// the data directory, or the detected defaults, with every directory that was set replacing its default
+    pub fn paths(&self) -> Paths {
+        let mut paths = match self.data_dir.as_ref() {
+            Some(data_dir) => Paths::within(data_dir),
+            None => Paths::detect(),
+        };
+        if let Some(models_dir) = self.models_dir.clone() {
+            paths.models = models_dir;
+        }
+        if let Some(config_dir) = self.config_dir.clone() {
+            paths.configs = config_dir;
+        }
+        if let Some(llama_bin) = self.llama_bin.clone() {
+            paths.llama_bin = llama_bin;
+        }
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &[&str]) -> Result<Args, String> {
+        Args::parse(arguments.iter().map(|argument| argument.to_string()))
+    }
+
+    #[test]
+    fn reads_options_both_ways() {
+        let args = parse(&["--model", "mistral", "--data-dir=/tmp/sulmo"]).unwrap();
+        assert_eq!(args.command, Command::Run);
+        assert_eq!(args.model.as_deref(), Some("mistral"));
+        assert_eq!(args.data_dir, Some(PathBuf::from("/tmp/sulmo")));
+        assert!(args.prompt.is_empty());
+
+        assert_eq!(parse(&[]).unwrap().command, Command::Run);
+        assert_eq!(parse(&["-V"]).unwrap().command, Command::Version);
+        assert_eq!(parse(&["--help"]).unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn rejects_unknown_arguments() {
+        assert_eq!(
+            parse(&["--colour"]).err().unwrap(),
+            "unknown argument '--colour'"
+        );
+        assert_eq!(parse(&["-x"]).err().unwrap(), "unknown argument '-x'");
+        assert_eq!(
+            parse(&["--colour=red"]).err().unwrap(),
+            "unknown argument '--colour=red'"
+        );
+        // words are only a prompt after 'ask'
+        assert_eq!(parse(&["hello"]).err().unwrap(), "unknown argument 'hello'");
+        assert_eq!(parse(&["ask", "ask"]).unwrap().prompt, ["ask"]);
+        assert_eq!(
+            parse(&["ask", "--verbose", "hi"]).err().unwrap(),
+            "unknown argument '--verbose'"
+        );
+    }
+
+    #[test]
+    fn asks_for_missing_values() {
+        assert_eq!(
+            parse(&["--model"]).err().unwrap(),
+            "--model expects a value"
+        );
+        assert_eq!(
+            parse(&["ask", "--models-dir"]).err().unwrap(),
+            "--models-dir expects a value"
+        );
+        // an empty inline value is still a value
+        assert_eq!(parse(&["--model="]).unwrap().model.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn gathers_the_prompt_after_ask() {
+        let args = parse(&["ask", "--model", "phi", "why", "is", "the", "sky", "blue?"]).unwrap();
+        assert_eq!(args.command, Command::Ask);
+        assert_eq!(args.model.as_deref(), Some("phi"));
+        assert_eq!(args.prompt, ["why", "is", "the", "sky", "blue?"]);
+
+        // the prompt is read from stdin when none is given
+        let args = parse(&["--model=phi", "ask"]).unwrap();
+        assert_eq!(args.command, Command::Ask);
+        assert!(args.prompt.is_empty());
+
+        let args = parse(&["ask", "define", "--", "--help", "-h"]).unwrap();
+        assert_eq!(args.command, Command::Ask);
+        assert_eq!(args.prompt, ["define", "--help", "-h"]);
+    }
+
+    #[test]
+    fn replaces_the_default_directories() {
+        let args = parse(&["--data-dir", "/data", "--config-dir", "/etc/sulmo"]).unwrap();
+        let paths = args.paths();
+        assert_eq!(paths.data, PathBuf::from("/data"));
+        assert_eq!(paths.models, PathBuf::from("/data/models"));
+        assert_eq!(paths.configs, PathBuf::from("/etc/sulmo"));
+        assert_eq!(paths.llama_bin, PathBuf::from("/data/llama-cpp/main"));
+    }
+}
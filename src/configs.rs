@@ -1,16 +1,138 @@
-use serde::{Deserialize, Serialize};
-use serde_json::{self};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{self, Map, Value};
 use std::{
     fs,
-    io::{Read, Write},
+    io::{self, Write},
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 use sysinfo::SystemExt;
 
-use crate::{conversation::ConversationChunk, utils::pathbuf_helper};
+use crate::{
+    backend::Message,
+    conversation::ConversationChunk,
+    paths,
+    templates::{ChatTemplate, TemplatePreset},
+};
+
+/// a config file whose layout is versioned, so that files written by older releases can be brought up to date
+pub trait Versioned: Default + Serialize + DeserializeOwned {
+    // version of the layout written by this release
+    const VERSION: u32;
+    /// turns the fields of a file written with the given version into those of the next version
+    fn migrate(_version: u32, _fields: &mut Map<String, Value>) {}
+}
+
+/// a config file that was read, see load_versioned
+pub struct Loaded<T> {
+    pub config: T,
+    // version of the layout the file was written with, 0 for files older than versioning
+    pub version: u32,
+    // fields whose value was invalid, their default is used instead
+    pub dropped: Vec<String>,
+    // the fields the file sets, after migrating them and leaving out invalid ones
+    pub fields: Map<String, Value>,
+}
+
+impl<T: Versioned> Loaded<T> {
+    /// files written by a newer release may hold fields this one doesn't know, they're never rewritten
+    pub fn newer(&self) -> bool {
+        self.version > T::VERSION
+    }
+    /// whether the file should be rewritten with the current layout
+    pub fn outdated(&self) -> bool {
+        !self.newer() && (self.version < T::VERSION || !self.dropped.is_empty())
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Missing,
+    // the file exists but couldn't be read, it's left alone
+    Unreadable(io::Error),
+    // the file isn't valid JSON, it has to be copied to a backup before being replaced
+    Broken(String),
+}
+
+/// reads a config file, migrating it from older versions and replacing invalid values with their defaults
+/// missing fields always take their default, unknown fields are ignored
+pub fn load_versioned<T: Versioned>(filepath: &Path) -> Result<Loaded<T>, LoadError> {
+    let buffer = fs::read(filepath).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => LoadError::Missing,
+        _ => LoadError::Unreadable(error),
+    })?;
+    let mut fields = match serde_json::from_slice::<Value>(&buffer) {
+        Ok(Value::Object(fields)) => fields,
+        Ok(_) => return Err(LoadError::Broken(String::from("expected an object"))),
+        Err(error) => return Err(LoadError::Broken(error.to_string())),
+    };
+    let version = fields.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    for from in version..T::VERSION {
+        T::migrate(from, &mut fields);
+    }
+    if version < T::VERSION {
+        fields.insert(String::from("version"), T::VERSION.into());
+    }
+
+    // a field that can't be read on its own is left out rather than discarding the whole file
+    let mut dropped = Vec::new();
+    if serde_json::from_value::<T>(Value::Object(fields.clone())).is_err() {
+        fields.retain(|key, value| {
+            let single = Value::Object(Map::from_iter([(key.clone(), value.clone())]));
+            let valid = serde_json::from_value::<T>(single).is_ok();
+            if !valid {
+                dropped.push(key.clone());
+            }
+            valid
+        });
+    }
+    let config = serde_json::from_value::<T>(Value::Object(fields.clone())).unwrap_or_default();
+    Ok(Loaded {
+        config,
+        version,
+        dropped,
+        fields,
+    })
+}
+
+/// reads a file holding a list, i.e. the personas
+pub fn load_library<T: DeserializeOwned>(filepath: &Path) -> Result<Vec<T>, LoadError> {
+    let buffer = fs::read(filepath).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => LoadError::Missing,
+        _ => LoadError::Unreadable(error),
+    })?;
+    serde_json::from_slice::<Vec<T>>(&buffer).map_err(|error| LoadError::Broken(error.to_string()))
+}
+
+pub fn save_library<T: Serialize>(filepath: &Path, library: &[T]) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(filepath)?;
+    file.write_all(serde_json::to_string_pretty(library).unwrap().as_bytes())?;
+    Ok(())
+}
+
+/// copies a config file to [FILE].[SECONDS].bak before it gets replaced, returns the copy's path
+pub fn backup(filepath: &Path) -> io::Result<PathBuf> {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut backup = filepath.as_os_str().to_owned();
+    backup.push(format!(".{}.bak", seconds));
+    let backup = PathBuf::from(backup);
+    fs::copy(filepath, &backup)?;
+    Ok(backup)
+}
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct AppConfig {
+    // layout of the file, see Versioned
+    version: u32,
+
     // maximum alloted time for a prompt to finish before it's killed
     pub timeout: f64,
 
@@ -24,6 +146,7 @@ pub struct AppConfig {
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            version: Self::VERSION,
             timeout: 420.0,
             tick_rate: 200,
             startup_freeze: 1000,
@@ -31,39 +154,76 @@ impl Default for AppConfig {
     }
 }
 
+impl Versioned for AppConfig {
+    const VERSION: u32 = 1;
+}
+
 impl AppConfig {
-    const FILEPATH: &'static str = "./configs/sulmo.conf";
-    fn to_pretty_json(&self) -> String {
-        serde_json::to_string_pretty(self).unwrap()
+    /// [CONFIG DIRECTORY]/sulmo.conf
+    pub fn filepath() -> PathBuf {
+        paths::get().configs.join("sulmo.conf")
     }
-    pub fn from_file() -> Option<Self> {
-        let mut file = fs::OpenOptions::new()
-            .read(true)
-            .open(Self::FILEPATH)
-            .ok()?;
-        let mut buffer: Vec<u8> = Vec::new();
-        file.read_to_end(&mut buffer).ok()?;
-        serde_json::from_slice::<Self>(&buffer).ok()
+    fn to_pretty_json(self) -> String {
+        serde_json::to_string_pretty(&self).unwrap()
+    }
+    pub fn from_file() -> Result<Loaded<Self>, LoadError> {
+        load_versioned(&Self::filepath())
     }
     pub fn save(&self) -> std::io::Result<()> {
         let mut file = fs::OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(Self::FILEPATH)?;
+            .open(Self::filepath())?;
         file.write_all(self.to_pretty_json().as_bytes())?;
         Ok(())
     }
-    pub fn to_print(&self) -> Vec<String> {
-        vec![
-            format!("generation timeout           :    '{}'", self.timeout),
-            format!("tick rate                    :    '{}'", self.tick_rate),
-        ]
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    // a new llama.cpp process for every prompt
+    #[default]
+    LlamaCpp,
+    // a llama.cpp server that keeps the model loaded between prompts
+    LlamaServer,
+    // any server exposing an OpenAI-compatible chat completions endpoint
+    #[serde(rename = "openai")]
+    OpenAi,
+}
+
+impl Backend {
+    // every backend as written in a config file
+    pub const NAMES: [&'static str; 3] = ["llama-cpp", "llama-server", "openai"];
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::LlamaCpp => "llama-cpp",
+            Self::LlamaServer => "llama-server",
+            Self::OpenAi => "openai",
+        }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ModelConfig {
+    // layout of the file, see Versioned
+    version: u32,
+
+    // what is used to run the model
+    pub backend: Backend,
+
+    // address of an already running llama.cpp server (i.e. http://127.0.0.1:8080), one is started when left empty
+    // with the openai backend, the base url of the API (i.e. http://127.0.0.1:11434/v1)
+    pub server_url: String,
+
+    // name of the model requested from an OpenAI-compatible API
+    remote_model: String,
+
+    // key sent to an OpenAI-compatible API, if any
+    api_key: String,
+
     // -n N, --n-predict N
     tokens_to_predict: i32,
 
@@ -82,6 +242,12 @@ pub struct ModelConfig {
     // --repeat-penalty N
     repeat_penalty: f64,
 
+    // how the conversation is laid out for the model, 'custom' uses the prompt prefix and suffix
+    pub template: TemplatePreset,
+
+    // standing instruction given to the model before the conversation, none when empty
+    pub system_prompt: String,
+
     // text that is always added before a prompt, space not included
     prompt_prefix: String,
 
@@ -91,15 +257,28 @@ pub struct ModelConfig {
     // should the text that is added before and after a prompt be displayed
     pub ps_displayed: bool,
 
+    // how many past exchanges are fed back to the model alongside a new prompt
+    pub history_depth: usize,
+
+    // -s N, --seed N, a new random seed is picked for every prompt when negative
+    pub seed: i64,
+
     // anything extra (i.e. --tfs 0.95)
     other: String,
 
+    // only read, conversations are stored in ./sessions
+    #[serde(skip_serializing)]
     past_chunks: Vec<ConversationChunk>,
 }
 
 impl Default for ModelConfig {
     fn default() -> Self {
         Self {
+            version: Self::VERSION,
+            backend: Backend::default(),
+            server_url: String::new(),
+            remote_model: String::new(),
+            api_key: String::new(),
             tokens_to_predict: -1,
             threads_used: {
                 let mut info = sysinfo::System::new();
@@ -111,17 +290,43 @@ impl Default for ModelConfig {
             prompt_context_size: 2048,
             randomness: 0.75,
             repeat_penalty: 1.15,
+            template: TemplatePreset::Auto,
+            system_prompt: String::new(),
             prompt_prefix: String::from("###Instruction: "),
             prompt_suffix: String::from(" ###Response: "),
             ps_displayed: false,
+            history_depth: 3,
+            seed: -1,
             other: String::from(""),
             past_chunks: Vec::new(),
         }
     }
 }
 
+impl Versioned for ModelConfig {
+    // 2: model config files only hold what differs from the defaults and rules, see ConfigLayers
+    const VERSION: u32 = 2;
+    fn migrate(version: u32, fields: &mut Map<String, Value>) {
+        // before chat templates, prompts were only wrapped in the prompt prefix and suffix
+        if version == 0 {
+            fields
+                .entry("template")
+                .or_insert(TemplatePreset::Custom.as_str().into());
+        }
+    }
+}
+
 impl ModelConfig {
-    pub const DEFAULT_FILEPATH: &'static str = "./configs/model.conf";
+    /// [CONFIG DIRECTORY]/model.conf, the defaults of every model
+    pub fn default_filepath() -> PathBuf {
+        paths::get().configs.join("model.conf")
+    }
+    /// [CONFIG DIRECTORY]/[MODEL FILE STEM].conf, the model's own layer, see ConfigLayers
+    pub fn filepath(model: &Path) -> PathBuf {
+        let mut filename = PathBuf::from(model.file_name().unwrap_or("?".as_ref()));
+        filename.set_extension("conf");
+        paths::get().configs.join(filename)
+    }
 
     pub fn to_args(&self) -> Vec<String> {
         let mut args = vec![
@@ -138,6 +343,10 @@ impl ModelConfig {
             "--repeat-penalty".to_string(),
             self.repeat_penalty.to_string(),
         ];
+        if self.seed >= 0 {
+            args.push("--seed".to_string());
+            args.push(self.seed.to_string());
+        }
         if !self.other.is_empty() {
             self.other
                 .split(' ')
@@ -145,17 +354,84 @@ impl ModelConfig {
         }
         args
     }
+    /// arguments used when starting a llama.cpp server, sampling parameters are sent with each request instead
+    pub fn to_server_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--threads".to_string(),
+            self.threads_used.to_string(),
+            "--n-gpu-layers".to_string(),
+            self.layers_offloaded_to_gpu.to_string(),
+            "--ctx-size".to_string(),
+            self.prompt_context_size.to_string(),
+        ];
+        if !self.other.is_empty() {
+            self.other
+                .split(' ')
+                .for_each(|slice| args.push(slice.to_string()));
+        }
+        args
+    }
+    /// body of a streamed request to a llama.cpp server's /completion endpoint
+    pub fn to_completion_request(&self, prompt: &str) -> serde_json::Value {
+        let mut request = serde_json::json!({
+            "prompt": prompt,
+            "n_predict": self.tokens_to_predict,
+            "temperature": self.randomness,
+            "repeat_penalty": self.repeat_penalty,
+            "cache_prompt": true,
+            "stream": true,
+        });
+        if self.seed >= 0 {
+            request["seed"] = self.seed.into();
+        }
+        request
+    }
+    /// body of a streamed request to an OpenAI-compatible /chat/completions endpoint
+    pub fn to_chat_request(&self, messages: &[Message]) -> serde_json::Value {
+        let mut request = serde_json::json!({
+            "model": self.remote_model,
+            "messages": messages,
+            "temperature": self.randomness,
+            "stream": true,
+            // the token counts come in a last chunk of their own
+            "stream_options": { "include_usage": true },
+        });
+        if self.tokens_to_predict > 0 {
+            request["max_tokens"] = self.tokens_to_predict.into();
+        }
+        if self.seed >= 0 {
+            request["seed"] = self.seed.into();
+        }
+        request
+    }
+    pub fn tokens_to_predict(&self) -> i32 {
+        self.tokens_to_predict
+    }
+    /// the parameters a reply is generated with, stored alongside it
+    pub fn sampling_snapshot(&self, template: TemplatePreset) -> SamplingSnapshot {
+        SamplingSnapshot {
+            backend: self.backend,
+            remote_model: self.remote_model.clone(),
+            template,
+            tokens_to_predict: self.tokens_to_predict,
+            prompt_context_size: self.prompt_context_size,
+            randomness: self.randomness,
+            repeat_penalty: self.repeat_penalty,
+            history_depth: self.history_depth,
+            other: self.other.clone(),
+        }
+    }
+    pub fn api_key(&self) -> Option<&str> {
+        Some(self.api_key.as_str()).filter(|key| !key.is_empty())
+    }
     fn to_pretty_json(&self) -> String {
         serde_json::to_string_pretty(self).unwrap()
     }
-    pub fn default_from_file() -> Option<Self> {
-        Self::from_file(Self::DEFAULT_FILEPATH)
+    pub fn default_from_file() -> Result<Loaded<Self>, LoadError> {
+        Self::from_file(Self::default_filepath())
     }
-    pub fn from_file<P: AsRef<Path>>(filepath: P) -> Option<Self> {
-        let mut file = fs::OpenOptions::new().read(true).open(filepath).ok()?;
-        let mut buffer: Vec<u8> = Vec::new();
-        file.read_to_end(&mut buffer).ok()?;
-        serde_json::from_slice::<Self>(&buffer).ok()
+    pub fn from_file<P: AsRef<Path>>(filepath: P) -> Result<Loaded<Self>, LoadError> {
+        load_versioned(filepath.as_ref())
     }
     pub fn save<P: AsRef<Path>>(&self, filepath: P) -> std::io::Result<()> {
         let mut file = fs::OpenOptions::new()
@@ -166,11 +442,43 @@ impl ModelConfig {
         file.write_all(self.to_pretty_json().as_bytes())?;
         Ok(())
     }
-    pub fn to_prompt(&self, prompt: &str) -> String {
-        format!("{}{}{}", self.prompt_prefix, prompt, self.prompt_suffix)
+    /// the layout used for the model, auto resolves to the preset matching the template embedded in the model file
+    pub fn chat_template(&self, embedded: Option<&str>) -> ChatTemplate {
+        self.resolve_template(embedded)
+            .template()
+            .unwrap_or_else(|| {
+                ChatTemplate::new(
+                    ["", "\n", &self.prompt_prefix, &self.prompt_suffix, "", "\n"],
+                    false,
+                )
+            })
+    }
+    pub fn resolve_template(&self, embedded: Option<&str>) -> TemplatePreset {
+        match self.template {
+            TemplatePreset::Auto => embedded
+                .and_then(TemplatePreset::detect)
+                .unwrap_or_default(),
+            preset => preset,
+        }
     }
     pub fn to_print(&self) -> Vec<String> {
         vec![
+            format!(
+                "backend                      :    '{}'",
+                self.backend.as_str()
+            ),
+            format!(
+                "server url                   :    '{}'",
+                self.server_url
+            ),
+            format!(
+                "remote model                 :    '{}'",
+                self.remote_model
+            ),
+            format!(
+                "api key                      :    '{}'",
+                "*".repeat(self.api_key.len().min(8))
+            ),
             format!(
                 "tokens to predict            :    '{}'",
                 self.tokens_to_predict
@@ -195,6 +503,12 @@ impl ModelConfig {
                 "repeat penalty               :    '{}'",
                 self.repeat_penalty
             ),
+            format!(
+                "chat template                :    '{}'", self.template.as_str()
+            ),
+            format!(
+                "system prompt                :    '{}'", self.system_prompt
+            ),
             format!(
                 "prompt prefix                :    '{}'", self.prompt_prefix
             ),
@@ -204,24 +518,208 @@ impl ModelConfig {
             format!(
                 "prefix/suffix displayed      :    '{}'", self.ps_displayed
             ),
+            format!(
+                "history depth                :    '{}'", self.history_depth
+            ),
+            format!(
+                "seed                         :    '{}'", self.seed
+            ),
             format!(
                 "other arguments              :    '{}'", self.other
             ),
         ]
     }
-    pub fn try_update<P: AsRef<Path>>(&mut self, model_filepath: P, other: &[ConversationChunk]) {
-        if other != self.past_chunks {
-            self.past_chunks = other.to_vec();
-            if let Some(filepath) = pathbuf_helper(
-                model_filepath.as_ref(),
-                &PathBuf::from("./configs/"),
-                "conf",
-            ) {
-                let _ = self.save(filepath);
-            }
+    /// the conversation older releases stored in the config file, moved into a session since
+    pub fn take_past_chunks(&mut self) -> Vec<ConversationChunk> {
+        std::mem::take(&mut self.past_chunks)
+    }
+}
+
+/// what a reply was generated with, see ModelConfig for the meaning of each field
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SamplingSnapshot {
+    pub backend: Backend,
+    // only set with the openai backend
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub remote_model: String,
+    // the preset in use, after auto was resolved
+    pub template: TemplatePreset,
+    pub tokens_to_predict: i32,
+    pub prompt_context_size: u16,
+    pub randomness: f64,
+    pub repeat_penalty: f64,
+    pub history_depth: usize,
+    pub other: String,
+}
+
+impl SamplingSnapshot {
+    pub fn to_print(&self) -> String {
+        let backend = if self.remote_model.is_empty() {
+            self.backend.as_str().to_string()
+        } else {
+            format!("{} ({})", self.backend.as_str(), self.remote_model)
+        };
+        let mut line = format!(
+            "{} · template {} · temperature {} · repeat penalty {} · tokens to predict {} · context {} · history {}",
+            backend,
+            self.template.as_str(),
+            self.randomness,
+            self.repeat_penalty,
+            self.tokens_to_predict,
+            self.prompt_context_size,
+            self.history_depth
+        );
+        if !self.other.is_empty() {
+            line.push_str(&format!(" · {}", self.other));
         }
+        line
+    }
+}
+
+/// a named system prompt that can be given to any model from the chat tab
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Persona {
+    pub name: String,
+    pub system_prompt: String,
+}
+
+impl Persona {
+    /// [CONFIG DIRECTORY]/personas.conf
+    pub fn filepath() -> PathBuf {
+        paths::get().configs.join("personas.conf")
+    }
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self {
+                name: String::from("assistant"),
+                system_prompt: String::from(
+                    "You are a helpful assistant. Answer clearly and concisely.",
+                ),
+            },
+            Self {
+                name: String::from("programmer"),
+                system_prompt: String::from(
+                    "You are an expert programmer. Reply with correct, idiomatic code and short explanations.",
+                ),
+            },
+            Self {
+                name: String::from("translator"),
+                system_prompt: String::from(
+                    "You are a translator. Translate every message into English, keeping its meaning and tone.",
+                ),
+            },
+        ]
     }
-    pub fn get_past_chunks(&self) -> Vec<ConversationChunk> {
-        self.past_chunks.clone()
+    pub fn library_from_file() -> Result<Vec<Self>, LoadError> {
+        load_library(&Self::filepath())
+    }
+    pub fn save_library(library: &[Self]) -> std::io::Result<()> {
+        save_library(&Self::filepath(), library)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    /// a config file of its own in the temporary config directory
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        mock::use_temporary_paths();
+        let configs = &paths::get().configs;
+        fs::create_dir_all(configs).unwrap();
+        let filepath = configs.join(format!("{}.conf", name));
+        fs::write(&filepath, contents).unwrap();
+        filepath
+    }
+
+    #[test]
+    fn drops_invalid_fields_only() {
+        let filepath = config_file(
+            "drops-invalid-fields",
+            r#"{"version":2,"randomness":"hot","seed":42,"history_depth":-1,"unknown":true}"#,
+        );
+        let loaded = load_versioned::<ModelConfig>(&filepath).unwrap();
+        let mut dropped = loaded.dropped.clone();
+        dropped.sort();
+        assert_eq!(dropped, vec!["history_depth", "randomness"]);
+        assert_eq!(loaded.config.seed, 42);
+        assert_eq!(loaded.config.randomness, ModelConfig::default().randomness);
+        assert!(!loaded.fields.contains_key("randomness"));
+        assert!(loaded.outdated());
+    }
+
+    #[test]
+    fn migrates_older_versions() {
+        let filepath = config_file("migrates-older-versions", r#"{"seed":7}"#);
+        let loaded = load_versioned::<ModelConfig>(&filepath).unwrap();
+        assert_eq!(loaded.version, 0);
+        assert!(loaded.outdated());
+        // files older than chat templates keep wrapping prompts in the prefix and suffix
+        assert_eq!(loaded.config.template, TemplatePreset::Custom);
+        assert_eq!(loaded.fields["version"], ModelConfig::VERSION);
+        assert_eq!(loaded.config.seed, 7);
+
+        let filepath = config_file("current-version", r#"{"version":2,"seed":7}"#);
+        let loaded = load_versioned::<ModelConfig>(&filepath).unwrap();
+        assert_eq!(loaded.config.template, TemplatePreset::Auto);
+        assert!(!loaded.outdated());
+    }
+
+    #[test]
+    fn never_touches_files_of_newer_releases() {
+        let filepath = config_file(
+            "newer-release",
+            r#"{"version":99,"timeout":30.0,"tick_rate":"fast","from_the_future":1}"#,
+        );
+        let loaded = load_versioned::<AppConfig>(&filepath).unwrap();
+        assert!(loaded.newer());
+        // even with an invalid value, it's left for the release that wrote it
+        assert_eq!(loaded.dropped, vec!["tick_rate"]);
+        assert!(!loaded.outdated());
+        assert_eq!(loaded.config.timeout, 30.0);
+    }
+
+    #[test]
+    fn tells_missing_and_broken_files_apart() {
+        mock::use_temporary_paths();
+        let missing = paths::get().configs.join("missing.conf");
+        assert!(matches!(
+            load_versioned::<AppConfig>(&missing),
+            Err(LoadError::Missing)
+        ));
+        let broken = config_file("broken", "{ not json");
+        assert!(matches!(
+            load_versioned::<AppConfig>(&broken),
+            Err(LoadError::Broken(_))
+        ));
+        let list = config_file("not-an-object", "[1, 2]");
+        assert!(matches!(
+            load_versioned::<AppConfig>(&list),
+            Err(LoadError::Broken(error)) if error == "expected an object"
+        ));
+        // reading a file never copies it, only replacing it does
+        assert!(!fs::read_dir(&paths::get().configs)
+            .unwrap()
+            .flatten()
+            .any(|entry| entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("broken.conf.")));
+    }
+
+    #[test]
+    fn backs_up_next_to_the_original() {
+        let filepath = config_file("backed-up", r#"{"version":1}"#);
+        let backup = backup(&filepath).unwrap();
+        let name = backup.file_name().unwrap().to_string_lossy().to_string();
+        let seconds = name
+            .strip_prefix("backed-up.conf.")
+            .and_then(|rest| rest.strip_suffix(".bak"))
+            .unwrap();
+        assert!(seconds.parse::<u64>().is_ok());
+        assert_eq!(backup.parent(), filepath.parent());
+        assert_eq!(fs::read_to_string(&backup).unwrap(), r#"{"version":1}"#);
+        assert_eq!(fs::read_to_string(&filepath).unwrap(), r#"{"version":1}"#);
     }
 }
@@ -1,98 +1,368 @@
-use crate::configs::{AppConfig, ModelConfig};
+use crate::{
+    backend::{
+        self, Generation, GenerationEvent, InferenceBackend, Message, Prompt, Role, StopReason,
+        Timings,
+    },
+    configs::{AppConfig, Backend, ModelConfig, Persona, SamplingSnapshot},
+    editor::Editor,
+    export::{self, ExportFormat},
+    gguf::GgufInfo,
+    history::History,
+    import,
+    layers::ConfigLayers,
+    sessions::Session,
+    templates::{ChatTemplate, TemplatePreset},
+    utils::format_timestamp,
+};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
-    io::Read,
-    path::PathBuf,
-    process::{Child, ChildStdout, Command, Stdio},
-    time::{Duration, Instant},
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use unicode_segmentation::UnicodeSegmentation;
 
 pub struct Conversation {
     pub model: PathBuf,
     pub config: ModelConfig,
-    // the conversation chunk that the user can modify
+    // what the config is assembled from, see ConfigLayers
+    pub layers: ConfigLayers,
+    // what the model file's header says about it, or why it couldn't be read
+    pub info: Result<GgufInfo, String>,
+    // what the user is typing, taken as the next input once sent
+    pub editor: Editor,
+    // the inputs sent in the current session, recalled into the editor
+    pub history: History,
+    // the conversation chunk put together from what the user sent
     usr_chunk: ConversationChunk,
     // the conversation chunk that may be being processed
     pro_chunk: ConversationChunk,
     past_chunks: Vec<ConversationChunk>,
-    stripped: bool,
-    buffer: [u8; 2048],
-    child: Option<(Child, ChildStdout, Instant)>,
+    // every session stored for the model, the past chunks and persona are those of the current one
+    sessions: Vec<Session>,
+    session_index: usize,
+    backend: Box<dyn InferenceBackend>,
+    // the kind of backend above, replaced before the next generation once the config asks for another
+    backend_kind: Backend,
+    generation: Option<(Generation, Instant)>,
+    // what the backend reported it's busy with before the first piece of text
+    status: String,
+    // replaces the model's system prompt while selected
+    persona: Option<Persona>,
 }
 
 impl Conversation {
-    pub fn new(model: PathBuf, config: ModelConfig) -> Self {
+    pub fn new(model: PathBuf, layers: ConfigLayers) -> Self {
+        let config = layers.resolve();
+        let backend = backend::from_config(&config);
+        Self::with_backend(model, config, layers, backend)
+    }
+    pub fn with_backend(
+        model: PathBuf,
+        config: ModelConfig,
+        layers: ConfigLayers,
+        backend: Box<dyn InferenceBackend>,
+    ) -> Self {
+        let mut sessions = Session::load_all(&model);
+        if sessions.is_empty() {
+            sessions.push(Session::new(&model, String::from("session 1")));
+        }
+        // picks up where the user left off
+        let session_index = sessions
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, session)| session.updated)
+            .map_or(0, |(index, _)| index);
         Self {
-            past_chunks: config.get_past_chunks(),
+            past_chunks: sessions[session_index].chunks.clone(),
+            persona: sessions[session_index].persona.clone(),
+            history: History::new(sessions[session_index].history.clone()),
+            sessions,
+            session_index,
+            info: match model.extension() {
+                Some(extension) if extension == "openai" => {
+                    Err("the model is served by an OpenAI-compatible API".to_string())
+                }
+                _ => GgufInfo::from_file(&model).map_err(|error| error.to_string()),
+            },
             model,
+            backend_kind: config.backend,
             config,
+            layers,
+            editor: Editor::default(),
             usr_chunk: ConversationChunk::new(),
             pro_chunk: ConversationChunk::new(),
-            stripped: false,
-            buffer: [0; 2048],
-            child: None,
-        }
-    }
-    pub fn run(&mut self) {
-        if self.child.is_none() {
-            let mut args: Vec<String> = self.config.to_args();
-
-            self.usr_chunk.input = self.config.to_prompt(&self.usr_chunk.raw_input);
-            args.push("--model".to_string());
-            args.push(self.model.to_str().unwrap().to_string());
-            args.push("--prompt".to_string());
-            args.push(self.usr_chunk.input.clone());
-
-            let mut child = Command::new("llama-cpp/main")
-                .args(args)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .stdin(Stdio::null())
-                .spawn()
-                .expect("failed to execute llama-cpp/main");
-
-            let child_stdout = child.stdout.take().unwrap();
-            self.child = Some((child, child_stdout, Instant::now()));
+            backend,
+            generation: None,
+            status: String::new(),
+        }
+    }
+    pub fn run(&mut self, app_config: &AppConfig) {
+        if self.generation.is_none() {
+            if self.backend_kind != self.config.backend {
+                self.backend = backend::from_config(&self.config);
+                self.backend_kind = self.config.backend;
+            }
             if !self.pro_chunk.raw_input.is_empty() {
-                self.past_chunks.push(self.pro_chunk.clone())
+                self.past_chunks.push(self.pro_chunk.clone());
+                self.pro_chunk.clear();
             };
+            self.usr_chunk.raw_input = self.editor.take();
+            self.history.push(&self.usr_chunk.raw_input);
+            self.usr_chunk.input = self.template().render(&[Message {
+                role: Role::User,
+                content: self.usr_chunk.raw_input.clone(),
+            }]);
+            let prompt = self.assemble_prompt();
+
+            // the seed is always chosen here so that it can be recorded and the reply reproduced
+            let mut config = self.config.clone();
+            if config.seed < 0 {
+                config.seed = random_seed().into();
+            }
+            self.usr_chunk.metadata = Some(ChunkMetadata {
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                model: self
+                    .model
+                    .file_name()
+                    .unwrap_or("?".as_ref())
+                    .to_string_lossy()
+                    .to_string(),
+                sampling: config
+                    .sampling_snapshot(self.config.resolve_template(self.embedded_template())),
+                seed: config.seed as u32,
+                duration: 0.0,
+                stop_reason: None,
+            });
+
             self.pro_chunk = self.usr_chunk.clone();
             self.usr_chunk.clear();
-            self.stripped = false;
+            self.status.clear();
+            match self.backend.generate(
+                &self.model,
+                &config,
+                &prompt,
+                Duration::from_secs_f64(app_config.timeout),
+            ) {
+                Ok(generation) => self.generation = Some((generation, Instant::now())),
+                Err(error) => {
+                    self.pro_chunk.error = format!("failed to start the generation, {}", error);
+                    self.finish_generation(StopReason::Error);
+                }
+            }
         }
     }
     pub fn check(&mut self, app_config: &AppConfig) {
-        self.config.try_update(&self.model, &self.past_chunks);
-        if let Some(child) = self.child.as_mut() {
-            if child.2.elapsed() > Duration::from_secs_f64(app_config.timeout) {
-                self.child = None;
-                self.past_chunks.push(self.pro_chunk.clone());
-                self.pro_chunk.clear();
-                return;
-            }
-            match child.1.read(&mut self.buffer) {
-                Ok(0) => {
-                    self.child = None;
-                    self.past_chunks.push(self.pro_chunk.clone());
-                    self.pro_chunk.clear();
-                }
-                Ok(n) => {
-                    let text_chunk = String::from_utf8_lossy(&self.buffer[..n]);
+        self.sync_session();
+        if self.generation.as_ref().is_some_and(|generation| {
+            generation.1.elapsed() > Duration::from_secs_f64(app_config.timeout)
+        }) {
+            self.finish_generation(StopReason::Timeout);
+            return;
+        }
+        // drains everything the generation thread has sent since the last tick
+        while let Some(event) = self
+            .generation
+            .as_ref()
+            .and_then(|generation| generation.0.try_next())
+        {
+            match event {
+                GenerationEvent::Text(text_chunk) => {
                     self.pro_chunk.output.push_str(&text_chunk);
-                    // a not very clean way of removing the echoed instruction fed to the LLM
-                    if !self.stripped && self.pro_chunk.output.len() >= self.pro_chunk.input.len() {
-                        let index = self.pro_chunk.input.len();
-                        self.pro_chunk.output = self.pro_chunk.output[index..].to_string();
-                        self.stripped = true;
-                    }
                 }
-                Err(error) => panic!("{}", error),
+                GenerationEvent::Status(status) => self.status = status,
+                GenerationEvent::Timings(timings) => self.pro_chunk.timings = Some(timings),
+                GenerationEvent::Warning(warning) => self.pro_chunk.warning = warning,
+                GenerationEvent::Finished(stop_reason) => self.finish_generation(stop_reason),
+                GenerationEvent::Failed(error) => {
+                    self.pro_chunk.error = error;
+                    self.finish_generation(StopReason::Error);
+                }
+            }
+        }
+    }
+    /// saves the current session if its chunks, persona or history changed
+    fn sync_session(&mut self) {
+        let session = &mut self.sessions[self.session_index];
+        if session.chunks != self.past_chunks
+            || session.persona != self.persona
+            || session.history != self.history.entries()
+        {
+            session.chunks = self.past_chunks.clone();
+            session.persona = self.persona.clone();
+            session.history = self.history.entries().to_vec();
+            let _ = session.save(&self.model);
+        }
+    }
+    pub fn get_sessions(&self) -> &[Session] {
+        &self.sessions
+    }
+    pub fn get_session_index(&self) -> usize {
+        self.session_index
+    }
+    /// stops the generation, saves the current session and continues the given one
+    pub fn switch_session(&mut self, index: usize) {
+        if index >= self.sessions.len() || index == self.session_index {
+            return;
+        }
+        self.reset_child();
+        self.sync_session();
+        self.session_index = index;
+        self.past_chunks = self.sessions[index].chunks.clone();
+        self.persona = self.sessions[index].persona.clone();
+        self.history = History::new(self.sessions[index].history.clone());
+    }
+    /// starts an empty session and switches to it, returns its index
+    pub fn new_session(&mut self) -> usize {
+        let mut session = Session::new(&self.model, format!("session {}", self.sessions.len() + 1));
+        let _ = session.save(&self.model);
+        self.sessions.push(session);
+        self.switch_session(self.sessions.len() - 1);
+        self.session_index
+    }
+    /// writes the session to ./exports, returns where it was written
+    pub fn export_session(&mut self, index: usize, format: ExportFormat) -> io::Result<PathBuf> {
+        self.sync_session();
+        match self.sessions.get(index) {
+            Some(session) => export::export(format, &self.model, &self.config, session),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+    /// stores every conversation found in the file as a new session, returns how many there were
+    pub fn import_file(&mut self, filepath: &Path) -> Result<usize, String> {
+        let conversations = import::read_file(filepath)?;
+        let count = conversations.len();
+        self.sync_session();
+        conversations.into_iter().for_each(|conversation| {
+            let mut session = Session::new(&self.model, conversation.name);
+            if let Some(created) = conversation.created {
+                session.created = created;
+            }
+            session.persona = conversation.system_prompt.map(|system_prompt| Persona {
+                name: String::from("imported"),
+                system_prompt,
+            });
+            session.chunks = conversation.chunks;
+            session.history = session.history_from_chunks();
+            // saved right away, new session ids have to be unique among the stored ones
+            let _ = session.save(&self.model);
+            self.sessions.push(session);
+        });
+        Ok(count)
+    }
+    /// sets the system prompt written for the session, none to fall back on the persona's or the model's
+    pub fn set_session_system_prompt(&mut self, index: usize, system_prompt: Option<String>) {
+        if let Some(session) = self.sessions.get_mut(index) {
+            session.system_prompt = system_prompt;
+            let _ = session.save(&self.model);
+        }
+    }
+    pub fn rename_session(&mut self, index: usize, name: String) {
+        if let Some(session) = self.sessions.get_mut(index) {
+            session.name = name;
+            let _ = session.save(&self.model);
+        }
+    }
+    /// deletes the session's file, an empty session is started when the last one is deleted
+    pub fn delete_session(&mut self, index: usize) {
+        if index >= self.sessions.len() {
+            return;
+        }
+        if index == self.session_index {
+            self.stop_generation();
+            self.pro_chunk.clear();
+        } else {
+            self.sync_session();
+        }
+        let _ = self.sessions.remove(index).delete(&self.model);
+        if self.sessions.is_empty() {
+            self.sessions
+                .push(Session::new(&self.model, String::from("session 1")));
+        }
+        if index < self.session_index || self.session_index >= self.sessions.len() {
+            self.session_index -= 1;
+        }
+        self.past_chunks = self.sessions[self.session_index].chunks.clone();
+        self.persona = self.sessions[self.session_index].persona.clone();
+        self.history = History::new(self.sessions[self.session_index].history.clone());
+    }
+    /// records how long the generation took and why it ended
+    fn conclude_metadata(&mut self, stop_reason: StopReason) {
+        let elapsed = self
+            .generation
+            .as_ref()
+            .map(|generation| generation.1.elapsed());
+        if let Some(metadata) = self.pro_chunk.metadata.as_mut() {
+            metadata.stop_reason = Some(stop_reason);
+            if let Some(elapsed) = elapsed {
+                metadata.duration = elapsed.as_secs_f64();
             }
         }
     }
-    pub fn get_usr_input(&self) -> &str {
-        self.usr_chunk.raw_input.as_str()
+    /// stops the generation if there is one and moves the processed chunk into the past ones
+    fn finish_generation(&mut self, stop_reason: StopReason) {
+        self.conclude_metadata(stop_reason);
+        self.stop_generation();
+        self.past_chunks.push(self.pro_chunk.clone());
+        self.pro_chunk.clear();
+    }
+    /// builds the prompt from the latest past exchanges followed by the user's new input
+    fn assemble_prompt(&self) -> Prompt {
+        let start = self
+            .past_chunks
+            .len()
+            .saturating_sub(self.config.history_depth);
+        Prompt::new(
+            &self.template(),
+            self.get_system_prompt(),
+            self.past_chunks[start..]
+                .iter()
+                .filter(|chunk| chunk.error.is_empty())
+                .map(|chunk| (chunk.raw_input.as_str(), chunk.output.as_str())),
+            &self.usr_chunk.raw_input,
+        )
+    }
+    /// the session's own system prompt, else the selected persona's, else the model's
+    pub fn get_system_prompt(&self) -> &str {
+        if let Some(system_prompt) = self.sessions[self.session_index].system_prompt.as_deref() {
+            return system_prompt;
+        }
+        match self.persona.as_ref() {
+            Some(persona) => persona.system_prompt.as_str(),
+            None => self.config.system_prompt.as_str(),
+        }
+    }
+    pub fn get_persona(&self) -> Option<&Persona> {
+        self.persona.as_ref()
+    }
+    pub fn set_persona(&mut self, persona: Option<Persona>) {
+        self.persona = persona;
+    }
+    fn embedded_template(&self) -> Option<&str> {
+        self.info
+            .as_ref()
+            .ok()
+            .and_then(|info| info.chat_template.as_deref())
+    }
+    fn template(&self) -> ChatTemplate {
+        self.config.chat_template(self.embedded_template())
+    }
+    /// the configured template, followed by the preset it resolved to when set to auto
+    pub fn get_template_name(&self) -> String {
+        match self.config.template {
+            TemplatePreset::Auto => format!(
+                "auto => {}",
+                self.config
+                    .resolve_template(self.embedded_template())
+                    .as_str()
+            ),
+            preset => preset.as_str().to_string(),
+        }
     }
     pub fn get_pro_input(&self) -> &str {
         if self.config.ps_displayed {
@@ -101,58 +371,50 @@ impl Conversation {
             self.pro_chunk.raw_input.as_str()
         }
     }
-    pub fn pop_back_input(&mut self) {
-        if !self.usr_chunk.raw_input.is_empty() {
-            if let Some(cluster) = self.usr_chunk.raw_input.graphemes(true).last() {
-                self.usr_chunk.raw_input = self
-                    .usr_chunk
-                    .raw_input
-                    .strip_suffix(cluster)
-                    .unwrap()
-                    .to_string()
-            }
-        }
-    }
     pub fn get_pro_output(&self) -> &str {
         self.pro_chunk.output.as_str()
     }
-    pub fn push_char(&mut self, chr: char) {
-        self.usr_chunk.raw_input.push(chr);
-    }
-    pub fn push_string(&mut self, string: &str) {
-        self.usr_chunk.raw_input.push_str(string);
+    /// changes a field of the model's own config file and saves it, the next generation uses the new value
+    /// none removes the model's own value so that the field follows the defaults and rules again
+    pub fn set_setting(&mut self, key: &str, value: Option<Value>) -> io::Result<()> {
+        self.layers.set(key, value);
+        self.config = self.layers.resolve();
+        self.layers.save(ModelConfig::filepath(&self.model))
     }
     pub fn reset_child(&mut self) {
-        self.child = None;
+        if self.generation.is_some() {
+            self.conclude_metadata(StopReason::UserStop);
+        }
+        self.stop_generation();
         if !self.pro_chunk.is_empty() {
             self.past_chunks.push(self.pro_chunk.clone());
             self.pro_chunk.clear();
         }
     }
-    pub fn get_past_conversations_str(&self) -> Vec<(&str, &str)> {
-        let mut vector: Vec<(&str, &str)> = Vec::new();
-        self.past_chunks.iter().for_each(|chunk| {
-            vector.push((
-                {
-                    if self.config.ps_displayed {
-                        chunk.input.as_str()
-                    } else {
-                        chunk.raw_input.as_str()
-                    }
-                },
-                chunk.output.as_str(),
-            ));
-        });
-        vector
+    pub fn get_past_chunks(&self) -> &[ConversationChunk] {
+        &self.past_chunks
+    }
+    /// what the backend is busy with, empty once text is being generated or when idle
+    pub fn get_status(&self) -> &str {
+        if self.generation.is_some() && self.pro_chunk.output.is_empty() {
+            self.status.as_str()
+        } else {
+            ""
+        }
     }
     pub fn pop_front(&mut self) {
-        self.child = None;
+        self.stop_generation();
         if self.pro_chunk.is_empty() {
             self.past_chunks.pop();
         } else {
             self.pro_chunk.clear();
         }
     }
+    fn stop_generation(&mut self) {
+        if let Some(mut generation) = self.generation.take() {
+            generation.0.stop();
+        }
+    }
     pub fn get_latest_output(&self) -> &str {
         if !self.pro_chunk.output.is_empty() {
             self.pro_chunk.output.as_str()
@@ -172,6 +434,18 @@ pub struct ConversationChunk {
     raw_input: String,
     // the output given by the LLM
     output: String,
+    // why the generation failed, empty if it didn't
+    #[serde(default)]
+    error: String,
+    // what went wrong without failing the generation, empty if nothing did
+    #[serde(default)]
+    warning: String,
+    // how fast the prompt was processed and the output generated, if the backend reported it
+    #[serde(default)]
+    timings: Option<Timings>,
+    // how the output was generated, absent from older and imported chunks
+    #[serde(default)]
+    metadata: Option<ChunkMetadata>,
 }
 
 impl ConversationChunk {
@@ -180,14 +454,274 @@ impl ConversationChunk {
             input: String::new(),
             raw_input: String::new(),
             output: String::new(),
+            error: String::new(),
+            warning: String::new(),
+            timings: None,
+            metadata: None,
+        }
+    }
+    /// an exchange that took place elsewhere, i.e. an imported one
+    pub fn from_exchange(
+        raw_input: String,
+        output: String,
+        error: String,
+        timings: Option<Timings>,
+    ) -> Self {
+        Self {
+            input: raw_input.clone(),
+            raw_input,
+            output,
+            error,
+            warning: String::new(),
+            timings,
+            metadata: None,
         }
     }
     fn clear(&mut self) {
         self.input.clear();
         self.raw_input.clear();
         self.output.clear();
+        self.error.clear();
+        self.warning.clear();
+        self.timings = None;
+        self.metadata = None;
     }
     fn is_empty(&self) -> bool {
-        self.raw_input.is_empty() || self.output.is_empty()
+        self.raw_input.is_empty() || (self.output.is_empty() && self.error.is_empty())
+    }
+    pub fn get_input(&self, ps_displayed: bool) -> &str {
+        if ps_displayed {
+            self.input.as_str()
+        } else {
+            self.raw_input.as_str()
+        }
+    }
+    pub fn get_output(&self) -> &str {
+        self.output.as_str()
+    }
+    pub fn get_error(&self) -> &str {
+        self.error.as_str()
+    }
+    pub fn get_warning(&self) -> &str {
+        self.warning.as_str()
+    }
+    pub fn get_timings(&self) -> Option<&Timings> {
+        self.timings.as_ref()
+    }
+    pub fn get_metadata(&self) -> Option<&ChunkMetadata> {
+        self.metadata.as_ref()
+    }
+}
+
+/// what's needed to reproduce or compare a reply
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ChunkMetadata {
+    // when the prompt was sent, in seconds since the unix epoch
+    pub timestamp: u64,
+    // file name of the model that replied
+    pub model: String,
+    pub sampling: SamplingSnapshot,
+    pub seed: u32,
+    // seconds between sending the prompt and the end of the reply
+    pub duration: f64,
+    // None while the reply is being generated
+    pub stop_reason: Option<StopReason>,
+}
+
+impl ChunkMetadata {
+    pub fn to_print(&self) -> Vec<String> {
+        vec![
+            format!(
+                "{} · {} · seed {} · {:.1}s · {}",
+                format_timestamp(self.timestamp),
+                self.model,
+                self.seed,
+                self.duration,
+                self.stop_reason
+                    .map_or("generating", |stop_reason| stop_reason.as_str())
+            ),
+            self.sampling.to_print(),
+        ]
+    }
+}
+
+/// a seed for llama.cpp, which takes 32 bits and uses the largest value to mean random
+fn random_seed() -> u32 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() % u32::MAX as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockBackend};
+    use serde_json::Map;
+
+    /// a conversation with a model of its own, so that tests running at once don't share sessions
+    fn conversation(name: &str, backend: &MockBackend) -> Conversation {
+        mock::use_temporary_paths();
+        let model = crate::paths::get().models.join(format!("{}.gguf", name));
+        let _ = std::fs::remove_dir_all(Session::directory(&model));
+        let layers = ConfigLayers::new(name, &Map::new(), &[], &Map::new());
+        Conversation::with_backend(model, layers.resolve(), layers, Box::new(backend.clone()))
+    }
+
+    fn send(conversation: &mut Conversation, input: &str) {
+        conversation.editor.set_text(input);
+        conversation.run(&AppConfig::default());
+    }
+
+    /// checks the conversation until the generation has ended or the condition holds
+    fn check_until(
+        conversation: &mut Conversation,
+        app_config: &AppConfig,
+        condition: impl Fn(&Conversation) -> bool,
+    ) {
+        let start = Instant::now();
+        while conversation.generation.is_some() && !condition(conversation) {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "the generation never ended"
+            );
+            conversation.check(app_config);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn last_chunk(conversation: &Conversation) -> &ConversationChunk {
+        conversation.get_past_chunks().last().unwrap()
+    }
+
+    fn stop_reason(chunk: &ConversationChunk) -> Option<StopReason> {
+        chunk
+            .get_metadata()
+            .and_then(|metadata| metadata.stop_reason)
+    }
+
+    #[test]
+    fn streams_a_reply() {
+        let timings = Timings {
+            prompt_tokens: 4,
+            generated_tokens: 2,
+            ..Timings::default()
+        };
+        let backend = MockBackend::new(vec![
+            GenerationEvent::Status("loading the model...".to_string()),
+            GenerationEvent::Text("Hello".to_string()),
+            GenerationEvent::Text(" there".to_string()),
+            GenerationEvent::Timings(timings.clone()),
+            GenerationEvent::Finished(StopReason::Eos),
+        ]);
+        let mut conversation = conversation("streams-a-reply", &backend);
+        send(&mut conversation, "hi");
+        check_until(&mut conversation, &AppConfig::default(), |_| false);
+
+        let chunk = last_chunk(&conversation);
+        assert_eq!(chunk.get_input(false), "hi");
+        assert_eq!(chunk.get_output(), "Hello there");
+        assert_eq!(chunk.get_error(), "");
+        assert_eq!(chunk.get_timings(), Some(&timings));
+        assert_eq!(stop_reason(chunk), Some(StopReason::Eos));
+        assert_eq!(conversation.get_status(), "");
+    }
+
+    #[test]
+    fn feeds_past_exchanges_back() {
+        let backend = MockBackend::new(vec![
+            GenerationEvent::Text("first reply".to_string()),
+            GenerationEvent::Finished(StopReason::Eos),
+        ]);
+        let mut conversation = conversation("feeds-past-exchanges-back", &backend);
+        send(&mut conversation, "first input");
+        check_until(&mut conversation, &AppConfig::default(), |_| false);
+        send(&mut conversation, "second input");
+        check_until(&mut conversation, &AppConfig::default(), |_| false);
+
+        let prompts = backend.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        assert!(!prompts[0].contains("first reply"));
+        assert!(prompts[1].contains("first input"));
+        assert!(prompts[1].contains("first reply"));
+        assert!(prompts[1].contains("second input"));
+    }
+
+    #[test]
+    fn shows_a_failure() {
+        let backend = MockBackend::new(vec![
+            GenerationEvent::Text("partial".to_string()),
+            GenerationEvent::Failed("the model could not be loaded".to_string()),
+        ]);
+        let mut conversation = conversation("shows-a-failure", &backend);
+        send(&mut conversation, "hi");
+        check_until(&mut conversation, &AppConfig::default(), |_| false);
+
+        let chunk = last_chunk(&conversation);
+        assert_eq!(chunk.get_output(), "partial");
+        assert_eq!(chunk.get_error(), "the model could not be loaded");
+        assert_eq!(stop_reason(chunk), Some(StopReason::Error));
+    }
+
+    #[test]
+    fn stops_when_asked() {
+        let backend = MockBackend {
+            hold: true,
+            ..MockBackend::new(vec![GenerationEvent::Text("partial".to_string())])
+        };
+        let mut conversation = conversation("stops-when-asked", &backend);
+        send(&mut conversation, "hi");
+        check_until(&mut conversation, &AppConfig::default(), |conversation| {
+            !conversation.get_pro_output().is_empty()
+        });
+        conversation.reset_child();
+
+        assert!(conversation.generation.is_none());
+        assert!(backend.cancel.is_set());
+        let chunk = last_chunk(&conversation);
+        assert_eq!(chunk.get_output(), "partial");
+        assert_eq!(stop_reason(chunk), Some(StopReason::UserStop));
+    }
+
+    #[test]
+    fn times_out() {
+        let backend = MockBackend {
+            hold: true,
+            ..MockBackend::new(vec![GenerationEvent::Text("partial".to_string())])
+        };
+        let mut app_config = AppConfig::default();
+        app_config.timeout = 0.05;
+        let mut conversation = conversation("times-out", &backend);
+        send(&mut conversation, "hi");
+        check_until(&mut conversation, &app_config, |_| false);
+
+        assert!(backend.cancel.is_set());
+        let chunk = last_chunk(&conversation);
+        assert_eq!(stop_reason(chunk), Some(StopReason::Timeout));
+    }
+
+    #[test]
+    fn prefers_the_session_system_prompt() {
+        let backend = MockBackend::new(vec![GenerationEvent::Finished(StopReason::Eos)]);
+        let mut conversation = conversation("prefers-the-session-system-prompt", &backend);
+        conversation.config.system_prompt = String::from("model prompt");
+        assert_eq!(conversation.get_system_prompt(), "model prompt");
+        conversation.set_persona(Some(Persona {
+            name: String::from("pirate"),
+            system_prompt: String::from("persona prompt"),
+        }));
+        assert_eq!(conversation.get_system_prompt(), "persona prompt");
+        conversation.set_session_system_prompt(0, Some(String::from("session prompt")));
+        assert_eq!(conversation.get_system_prompt(), "session prompt");
+
+        send(&mut conversation, "hi");
+        check_until(&mut conversation, &AppConfig::default(), |_| false);
+        let prompts = backend.prompts.lock().unwrap();
+        assert!(prompts[0].contains("session prompt"));
+        assert!(!prompts[0].contains("persona prompt"));
     }
 }
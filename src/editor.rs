@@ -0,0 +1,371 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// the text typed in the chat tab, it can span several lines and the cursor can be moved anywhere in it
+#[derive(Clone, Debug, Default)]
+pub struct Editor {
+    text: String,
+    // byte index in the text, always on a grapheme boundary
+    cursor: usize,
+    // the column moving up and down aims for, kept until the cursor is moved any other way
+    goal_column: Option<usize>,
+    // the width the text was last wrapped to, moving up and down follows the rows as displayed
+    width: usize,
+    // the first row shown when the text doesn't fit in the box
+    scroll: usize,
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .any(|chr| chr.is_alphanumeric() || chr == '_')
+}
+
+impl Editor {
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+    /// replaces the text, the cursor is put at its end
+    pub fn set_text(&mut self, text: &str) {
+        self.text.clear();
+        self.cursor = 0;
+        self.scroll = 0;
+        self.insert_str(text);
+    }
+    /// empties the editor, returning what was typed in
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        self.goal_column = None;
+        self.scroll = 0;
+        std::mem::take(&mut self.text)
+    }
+    pub fn insert_char(&mut self, chr: char) {
+        let mut buffer = [0; 4];
+        self.insert_str(chr.encode_utf8(&mut buffer));
+    }
+    /// carriage returns are dropped and tabs become spaces, so that pasted text shows the way it's sent
+    pub fn insert_str(&mut self, string: &str) {
+        let string = string
+            .replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .replace('\t', "    ");
+        self.text.insert_str(self.cursor, &string);
+        self.cursor += string.len();
+        self.goal_column = None;
+    }
+    /// removes the grapheme before the cursor
+    pub fn backspace(&mut self) {
+        if let Some((index, _)) = self.text[..self.cursor].grapheme_indices(true).next_back() {
+            self.text.replace_range(index..self.cursor, "");
+            self.cursor = index;
+        }
+        self.goal_column = None;
+    }
+    /// removes the grapheme after the cursor
+    pub fn delete(&mut self) {
+        if let Some(grapheme) = self.text[self.cursor..].graphemes(true).next() {
+            let end = self.cursor + grapheme.len();
+            self.text.replace_range(self.cursor..end, "");
+        }
+        self.goal_column = None;
+    }
+    /// removes everything from the start of the word before the cursor up to the cursor
+    pub fn delete_word_before(&mut self) {
+        let end = self.cursor;
+        self.word_left();
+        self.text.replace_range(self.cursor..end, "");
+    }
+    pub fn left(&mut self) {
+        if let Some((index, _)) = self.text[..self.cursor].grapheme_indices(true).next_back() {
+            self.cursor = index;
+        }
+        self.goal_column = None;
+    }
+    pub fn right(&mut self) {
+        if let Some(grapheme) = self.text[self.cursor..].graphemes(true).next() {
+            self.cursor += grapheme.len();
+        }
+        self.goal_column = None;
+    }
+    /// to the start of the word before the cursor
+    pub fn word_left(&mut self) {
+        let graphemes: Vec<(usize, &str)> =
+            self.text[..self.cursor].grapheme_indices(true).collect();
+        let mut index = graphemes.len();
+        while index > 0 && !is_word(graphemes[index - 1].1) {
+            index -= 1;
+        }
+        while index > 0 && is_word(graphemes[index - 1].1) {
+            index -= 1;
+        }
+        self.cursor = graphemes.get(index).map_or(0, |(start, _)| *start);
+        self.goal_column = None;
+    }
+    /// to the end of the word after the cursor
+    pub fn word_right(&mut self) {
+        let after = &self.text[self.cursor..];
+        let mut graphemes = after.grapheme_indices(true).peekable();
+        while graphemes
+            .next_if(|(_, grapheme)| !is_word(grapheme))
+            .is_some()
+        {}
+        while graphemes
+            .next_if(|(_, grapheme)| is_word(grapheme))
+            .is_some()
+        {}
+        self.cursor += graphemes.peek().map_or(after.len(), |(index, _)| *index);
+        self.goal_column = None;
+    }
+    /// to the start of the line
+    pub fn home(&mut self) {
+        self.cursor = self.text[..self.cursor]
+            .rfind('\n')
+            .map_or(0, |index| index + 1);
+        self.goal_column = None;
+    }
+    /// to the end of the line
+    pub fn end(&mut self) {
+        self.cursor += self.text[self.cursor..]
+            .find('\n')
+            .unwrap_or(self.text.len() - self.cursor);
+        self.goal_column = None;
+    }
+    /// to the row above as displayed, false if the cursor is already on the first one
+    pub fn up(&mut self) -> bool {
+        let rows = self.rows();
+        let (row, column) = self.locate(&rows);
+        if row == 0 {
+            return false;
+        }
+        self.move_to_row(&rows, row - 1, column);
+        true
+    }
+    /// to the row below as displayed, false if the cursor is already on the last one
+    pub fn down(&mut self) -> bool {
+        let rows = self.rows();
+        let (row, column) = self.locate(&rows);
+        if row + 1 >= rows.len() {
+            return false;
+        }
+        self.move_to_row(&rows, row + 1, column);
+        true
+    }
+    /// how many rows the text takes up once wrapped to the width, which is kept for moving up and down
+    pub fn wrap(&mut self, width: usize) -> usize {
+        self.width = width;
+        self.rows().len()
+    }
+    /// the rows that fit in a box of the given height, scrolled so that the cursor is among them,
+    /// and where the cursor is within them as a column and a row
+    pub fn view(&mut self, height: usize) -> (Vec<String>, (usize, usize)) {
+        let rows = self.rows();
+        let (row, column) = self.locate(&rows);
+        let height = height.max(1);
+        if row < self.scroll {
+            self.scroll = row;
+        } else if row >= self.scroll + height {
+            self.scroll = row + 1 - height;
+        }
+        self.scroll = self.scroll.min(rows.len().saturating_sub(height));
+        let lines = rows
+            .iter()
+            .skip(self.scroll)
+            .take(height)
+            .map(|range| self.text[range.clone()].to_string())
+            .collect();
+        (lines, (column, row - self.scroll))
+    }
+    /// the text split into the rows it's displayed as, long lines are broken after a space when possible
+    fn rows(&self) -> Vec<Range<usize>> {
+        // one column is kept free so that the cursor fits after a full row
+        let width = self.width.saturating_sub(1).max(1);
+        let mut rows = Vec::new();
+        let mut offset = 0;
+        for line in self.text.split('\n') {
+            let mut start = offset;
+            let mut row_width = 0;
+            // right after the last whitespace of the row, where it can be broken without splitting a word
+            let mut last_break = None;
+            for (index, grapheme) in line.grapheme_indices(true) {
+                let index = offset + index;
+                let grapheme_width = grapheme.width();
+                if row_width + grapheme_width > width && index > start {
+                    let end = match last_break {
+                        Some(end)
+                            if end > start
+                                && self.text[end..index].width() + grapheme_width <= width =>
+                        {
+                            end
+                        }
+                        _ => index,
+                    };
+                    rows.push(start..end);
+                    start = end;
+                    row_width = self.text[start..index].width();
+                    last_break = None;
+                }
+                row_width += grapheme_width;
+                if grapheme.chars().all(char::is_whitespace) {
+                    last_break = Some(index + grapheme.len());
+                }
+            }
+            rows.push(start..offset + line.len());
+            offset += line.len() + 1;
+        }
+        rows
+    }
+    /// whether a row is the last one of its line, a cursor at its end stays on it rather than going to the next row
+    fn ends_line(&self, row: &Range<usize>) -> bool {
+        row.end == self.text.len() || self.text[row.end..].starts_with('\n')
+    }
+    /// the row the cursor is displayed on and its column
+    fn locate(&self, rows: &[Range<usize>]) -> (usize, usize) {
+        let row = rows
+            .iter()
+            .position(|row| {
+                row.start <= self.cursor
+                    && (self.cursor < row.end || (self.cursor == row.end && self.ends_line(row)))
+            })
+            .unwrap_or(rows.len().saturating_sub(1));
+        let start = rows.get(row).map_or(0, |row| row.start);
+        (row, self.text[start..self.cursor].width())
+    }
+    fn move_to_row(&mut self, rows: &[Range<usize>], row: usize, column: usize) {
+        let goal = *self.goal_column.get_or_insert(column);
+        let range = rows[row].clone();
+        // the end of a wrapped row is displayed at the start of the next one
+        let last = if self.ends_line(&range) {
+            range.end
+        } else {
+            self.text[range.clone()]
+                .grapheme_indices(true)
+                .next_back()
+                .map_or(range.start, |(index, _)| range.start + index)
+        };
+        let mut cursor = range.start;
+        let mut width = 0;
+        for (index, grapheme) in self.text[range.clone()].grapheme_indices(true) {
+            width += grapheme.width();
+            if width > goal || range.start + index >= last {
+                break;
+            }
+            cursor = range.start + index + grapheme.len();
+        }
+        self.cursor = cursor.min(last);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> Editor {
+        let mut editor = Editor::default();
+        editor.set_text(text);
+        editor
+    }
+
+    /// the text with a '|' where the cursor is
+    fn shown(editor: &Editor) -> String {
+        let mut text = editor.text.clone();
+        text.insert(editor.cursor, '|');
+        text
+    }
+
+    #[test]
+    fn moves_by_grapheme() {
+        let mut editor = editor("ae\u{301}日");
+        editor.left();
+        assert_eq!(shown(&editor), "ae\u{301}|日");
+        editor.left();
+        assert_eq!(shown(&editor), "a|e\u{301}日");
+        editor.left();
+        editor.left();
+        assert_eq!(shown(&editor), "|ae\u{301}日");
+        editor.right();
+        assert_eq!(shown(&editor), "a|e\u{301}日");
+    }
+
+    #[test]
+    fn jumps_over_words() {
+        let mut editor = editor("let snake_case = 42;");
+        editor.word_left();
+        assert_eq!(shown(&editor), "let snake_case = |42;");
+        editor.word_left();
+        assert_eq!(shown(&editor), "let |snake_case = 42;");
+        editor.word_left();
+        editor.word_left();
+        assert_eq!(shown(&editor), "|let snake_case = 42;");
+        editor.word_right();
+        assert_eq!(shown(&editor), "let| snake_case = 42;");
+        editor.word_right();
+        assert_eq!(shown(&editor), "let snake_case| = 42;");
+    }
+
+    #[test]
+    fn edits_in_the_middle() {
+        let mut editor = editor("hello world");
+        editor.word_left();
+        editor.insert_str("big ");
+        assert_eq!(shown(&editor), "hello big |world");
+        editor.backspace();
+        assert_eq!(shown(&editor), "hello big|world");
+        editor.delete();
+        assert_eq!(shown(&editor), "hello big|orld");
+        editor.delete_word_before();
+        assert_eq!(shown(&editor), "hello |orld");
+        editor.insert_char('w');
+        assert_eq!(shown(&editor), "hello w|orld");
+        assert_eq!(editor.take(), "hello world");
+        assert_eq!(shown(&editor), "|");
+    }
+
+    #[test]
+    fn handles_new_lines() {
+        let mut editor = editor("one\r\ntwo\tthree");
+        assert_eq!(editor.text(), "one\ntwo    three");
+        editor.home();
+        assert_eq!(shown(&editor), "one\n|two    three");
+        editor.left();
+        assert_eq!(shown(&editor), "one|\ntwo    three");
+        editor.home();
+        editor.end();
+        assert_eq!(shown(&editor), "one|\ntwo    three");
+        editor.wrap(80);
+        assert!(editor.down());
+        assert_eq!(shown(&editor), "one\ntwo|    three");
+        assert!(!editor.down());
+        assert!(editor.up());
+        assert!(!editor.up());
+    }
+
+    #[test]
+    fn keeps_the_column_moving_up_and_down() {
+        let mut editor = editor("a long line\nab\nanother long line");
+        editor.wrap(80);
+        assert!(editor.up());
+        assert_eq!(shown(&editor), "a long line\nab|\nanother long line");
+        assert!(editor.up());
+        assert_eq!(shown(&editor), "a long line|\nab\nanother long line");
+    }
+
+    #[test]
+    fn wraps_to_the_width() {
+        let mut editor = editor("the quick brown fox");
+        // a column is kept for the cursor, so rows hold 10 columns
+        assert_eq!(editor.wrap(11), 2);
+        assert_eq!(editor.wrap(80), 1);
+        assert_eq!(editor.wrap(4), 7);
+        editor.set_text("a\n\nb");
+        assert_eq!(editor.wrap(80), 3);
+        editor.set_text("");
+        assert_eq!(editor.wrap(80), 1);
+
+        let mut editor = self::editor("the quick brown fox");
+        editor.wrap(11);
+        let (rows, (column, row)) = editor.view(1);
+        assert_eq!(rows, vec!["brown fox"]);
+        assert_eq!((column, row), (9, 0));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{configs::ModelConfig, paths, sessions::Session, utils::format_timestamp};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// [DATA DIRECTORY]/exports
+pub fn directory() -> PathBuf {
+    paths::get().data.join("exports")
+}
+
+/// writes the session to [DATA DIRECTORY]/exports/[MODEL]-[SESSION]-[ID].[EXTENSION], returns the file's path
+pub fn export(
+    format: ExportFormat,
+    model: &Path,
+    config: &ModelConfig,
+    session: &Session,
+) -> io::Result<PathBuf> {
+    let transcript = Transcript::new(model, config, session);
+    let contents = match format {
+        ExportFormat::Markdown => transcript.to_markdown(),
+        ExportFormat::Html => transcript.to_html(),
+        ExportFormat::Json => transcript.to_json(),
+    };
+    fs::create_dir_all(directory())?;
+    let filepath = directory().join(format!(
+        "{}-{}-{}.{}",
+        sanitize(&model.file_stem().unwrap_or("?".as_ref()).to_string_lossy()),
+        sanitize(&session.name),
+        session.id,
+        format.extension()
+    ));
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(&filepath)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(filepath)
+}
+
+/// keeps file names portable, i.e. 'What is Rust?' => 'What-is-Rust'
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|chr| {
+            if chr.is_alphanumeric() || chr == '-' || chr == '_' || chr == '.' {
+                chr
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = name.trim_matches(['-', '.']);
+    if name.is_empty() {
+        String::from("session")
+    } else {
+        name.to_string()
+    }
+}
+
+/// what every format is built from
+struct Transcript<'a> {
+    model: String,
+    config: &'a ModelConfig,
+    session: &'a Session,
+    system_prompt: &'a str,
+}
+
+impl<'a> Transcript<'a> {
+    fn new(model: &Path, config: &'a ModelConfig, session: &'a Session) -> Self {
+        Self {
+            model: model
+                .file_name()
+                .unwrap_or("?".as_ref())
+                .to_string_lossy()
+                .to_string(),
+            config,
+            session,
+            system_prompt: match (session.system_prompt.as_deref(), session.persona.as_ref()) {
+                (Some(system_prompt), _) => system_prompt,
+                (None, Some(persona)) => persona.system_prompt.as_str(),
+                (None, None) => config.system_prompt.as_str(),
+            },
+        }
+    }
+    fn header(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("model                        :    '{}'", self.model),
+            format!(
+                "created                      :    '{}'",
+                format_timestamp(self.session.created)
+            ),
+        ];
+        if let Some(persona) = self.session.persona.as_ref() {
+            lines.push(format!(
+                "persona                      :    '{}'",
+                persona.name
+            ));
+        }
+        lines
+    }
+    fn to_markdown(&self) -> String {
+        let mut text = format!("# {}\n\n", self.session.name);
+        self.header()
+            .iter()
+            .for_each(|line| text.push_str(&format!("    {}\n", line)));
+        text.push_str("\n## Parameters\n\n");
+        self.config
+            .to_print()
+            .iter()
+            .for_each(|line| text.push_str(&format!("    {}\n", line)));
+        if !self.system_prompt.is_empty() {
+            text.push_str(&format!("\n## System\n\n{}\n", self.system_prompt));
+        }
+        self.session.chunks.iter().for_each(|chunk| {
+            text.push_str(&format!("\n## User\n\n{}\n", chunk.get_input(false)));
+            text.push_str(&format!("\n## {}\n\n", self.model));
+            if !chunk.get_output().is_empty() {
+                text.push_str(&format!("{}\n", chunk.get_output()));
+            }
+            if !chunk.get_error().is_empty() {
+                text.push_str(&format!("\n> **error:** {}\n", chunk.get_error()));
+            }
+            if let Some(timings) = chunk.get_timings() {
+                text.push_str(&format!("\n*{}*\n", timings));
+            }
+        });
+        text
+    }
+    fn to_html(&self) -> String {
+        let mut body = format!(
+            "<h1>{}</h1>\n<pre class=\"meta\">",
+            escape(&self.session.name)
+        );
+        self.header()
+            .iter()
+            .chain(self.config.to_print().iter())
+            .for_each(|line| body.push_str(&format!("{}\n", escape(line))));
+        body.push_str("</pre>\n");
+        if !self.system_prompt.is_empty() {
+            body.push_str(&message_html("system", "System", self.system_prompt, ""));
+        }
+        self.session.chunks.iter().for_each(|chunk| {
+            body.push_str(&message_html("user", "User", chunk.get_input(false), ""));
+            let mut extra = String::new();
+            if !chunk.get_error().is_empty() {
+                extra.push_str(&format!(
+                    "<p class=\"error\">{}</p>",
+                    escape(chunk.get_error())
+                ));
+            }
+            if let Some(timings) = chunk.get_timings() {
+                extra.push_str(&format!(
+                    "<p class=\"timings\">{}</p>",
+                    escape(&timings.to_string())
+                ));
+            }
+            body.push_str(&message_html(
+                "assistant",
+                &self.model,
+                chunk.get_output(),
+                &extra,
+            ));
+        });
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            escape(&self.session.name),
+            STYLESHEET,
+            body
+        )
+    }
+    fn to_json(&self) -> String {
+        let mut config = serde_json::to_value(self.config).unwrap_or_default();
+        // keys are never shared along with a transcript
+        if let Some(config) = config.as_object_mut() {
+            config.remove("api_key");
+        }
+        let mut messages = Vec::new();
+        if !self.system_prompt.is_empty() {
+            messages.push(serde_json::json!({
+                "role": "system",
+                "content": self.system_prompt,
+            }));
+        }
+        self.session.chunks.iter().for_each(|chunk| {
+            messages.push(serde_json::json!({
+                "role": "user",
+                "content": chunk.get_input(false),
+            }));
+            let mut reply = serde_json::json!({
+                "role": "assistant",
+                "content": chunk.get_output(),
+            });
+            if !chunk.get_error().is_empty() {
+                reply["error"] = chunk.get_error().into();
+            }
+            if let Some(timings) = chunk.get_timings() {
+                reply["timings"] = serde_json::to_value(timings).unwrap_or_default();
+            }
+            if let Some(metadata) = chunk.get_metadata() {
+                reply["metadata"] = serde_json::to_value(metadata).unwrap_or_default();
+            }
+            messages.push(reply);
+        });
+        serde_json::to_string_pretty(&serde_json::json!({
+            "model": self.model,
+            "session": self.session.name,
+            "created": self.session.created,
+            "persona": self.session.persona.as_ref().map(|persona| persona.name.as_str()),
+            "config": config,
+            "messages": messages,
+        }))
+        .unwrap()
+    }
+}
+
+const STYLESHEET: &str = "body { max-width: 52em; margin: 2em auto; padding: 0 1em; font-family: sans-serif; color: #222; }
+h1 { color: #00a1b9; }
+pre.meta { color: #666; font-size: 0.85em; }
+.message { margin: 1em 0; padding: 0.6em 1em; border-radius: 8px; }
+.message h2 { margin: 0 0 0.4em 0; font-size: 0.9em; }
+.message .content { white-space: pre-wrap; }
+.system { background: #f3f3f3; }
+.user { background: #e6f6f8; margin-left: 4em; }
+.assistant { background: #e6f7e8; margin-right: 4em; }
+.error { color: #cd3131; }
+.timings { color: #888; font-size: 0.85em; }
+";
+
+fn message_html(class: &str, author: &str, content: &str, extra: &str) -> String {
+    format!(
+        "<div class=\"message {}\">\n<h2>{}</h2>\n<div class=\"content\">{}</div>{}\n</div>\n",
+        class,
+        escape(author),
+        escape(content),
+        extra
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
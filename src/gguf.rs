@@ -0,0 +1,463 @@
+use std::{
+    fs,
+    io::{self, BufReader, Read, Seek},
+    path::Path,
+};
+
+/// what a GGUF file says about the model it contains, read from its header without loading any tensor
+#[derive(Clone, Debug, Default)]
+pub struct GgufInfo {
+    pub version: u32,
+    pub file_size: u64,
+    pub tensor_count: u64,
+    pub metadata_count: u64,
+    pub name: Option<String>,
+    pub architecture: Option<String>,
+    pub parameter_count: Option<u64>,
+    pub file_type: Option<u32>,
+    pub context_length: Option<u64>,
+    pub chat_template: Option<String>,
+}
+
+// value types, as numbered by the GGUF specification
+const UINT8: u32 = 0;
+const INT8: u32 = 1;
+const UINT16: u32 = 2;
+const INT16: u32 = 3;
+const UINT32: u32 = 4;
+const INT32: u32 = 5;
+const FLOAT32: u32 = 6;
+const BOOL: u32 = 7;
+const STRING: u32 = 8;
+const ARRAY: u32 = 9;
+const UINT64: u32 = 10;
+const INT64: u32 = 11;
+const FLOAT64: u32 = 12;
+
+// longer strings are taken for a corrupted file rather than read into memory, chat templates are far shorter
+const MAX_STRING_LENGTH: u64 = 1 << 24;
+// arrays of arrays are skipped recursively, a crafted file mustn't be able to exhaust the stack
+const MAX_ARRAY_NESTING: usize = 8;
+
+enum Value {
+    Unsigned(u64),
+    Signed(i64),
+    String(String),
+    Other,
+}
+
+struct GgufReader<R: Read + Seek> {
+    // buffered here so that skipping a value doesn't throw the buffer away, vocabularies hold many short strings
+    reader: BufReader<R>,
+    version: u32,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<R: Read + Seek> GgufReader<R> {
+    fn read_bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buffer = [0; N];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+    fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.read_bytes()?))
+    }
+    fn read_u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.read_bytes()?))
+    }
+    /// counts and lengths were 32 bits wide in the first version of the format
+    fn read_count(&mut self) -> io::Result<u64> {
+        if self.version == 1 {
+            self.read_u32().map(u64::from)
+        } else {
+            self.read_u64()
+        }
+    }
+    fn read_string(&mut self) -> io::Result<String> {
+        let length = self.read_count()?;
+        if length > MAX_STRING_LENGTH {
+            return Err(invalid_data("a metadata string is too long"));
+        }
+        let mut buffer = Vec::new();
+        (&mut self.reader).take(length).read_to_end(&mut buffer)?;
+        if buffer.len() as u64 != length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(String::from_utf8_lossy(&buffer).to_string())
+    }
+    fn skip(&mut self, length: u64) -> io::Result<()> {
+        let length =
+            i64::try_from(length).map_err(|_| invalid_data("a metadata value is too long"))?;
+        self.reader.seek_relative(length)
+    }
+    /// skips over the items of an array, the lengths come from the file and can't be trusted
+    fn skip_array(&mut self, item_type: u32, length: u64, depth: usize) -> io::Result<()> {
+        let item_size = match item_type {
+            UINT8 | INT8 | BOOL => 1,
+            UINT16 | INT16 => 2,
+            UINT32 | INT32 | FLOAT32 => 4,
+            UINT64 | INT64 | FLOAT64 => 8,
+            STRING => {
+                for _ in 0..length {
+                    let length = self.read_count()?;
+                    self.skip(length)?;
+                }
+                return Ok(());
+            }
+            ARRAY => {
+                if depth >= MAX_ARRAY_NESTING {
+                    return Err(invalid_data("metadata arrays are nested too deeply"));
+                }
+                for _ in 0..length {
+                    let item_type = self.read_u32()?;
+                    let length = self.read_count()?;
+                    self.skip_array(item_type, length, depth + 1)?;
+                }
+                return Ok(());
+            }
+            _ => {
+                return Err(invalid_data(&format!(
+                    "unknown metadata value type {}",
+                    item_type
+                )))
+            }
+        };
+        let size = length
+            .checked_mul(item_size)
+            .ok_or_else(|| invalid_data("a metadata array is too long"))?;
+        self.skip(size)
+    }
+    fn read_value(&mut self, value_type: u32) -> io::Result<Value> {
+        Ok(match value_type {
+            UINT8 => Value::Unsigned(u8::from_le_bytes(self.read_bytes()?).into()),
+            INT8 => Value::Signed(i8::from_le_bytes(self.read_bytes()?).into()),
+            UINT16 => Value::Unsigned(u16::from_le_bytes(self.read_bytes()?).into()),
+            INT16 => Value::Signed(i16::from_le_bytes(self.read_bytes()?).into()),
+            UINT32 => Value::Unsigned(self.read_u32()?.into()),
+            INT32 => Value::Signed(i32::from_le_bytes(self.read_bytes()?).into()),
+            UINT64 => Value::Unsigned(self.read_u64()?),
+            INT64 => Value::Signed(i64::from_le_bytes(self.read_bytes()?)),
+            FLOAT32 | BOOL => {
+                self.skip(if value_type == BOOL { 1 } else { 4 })?;
+                Value::Other
+            }
+            FLOAT64 => {
+                self.skip(8)?;
+                Value::Other
+            }
+            STRING => Value::String(self.read_string()?),
+            ARRAY => {
+                let item_type = self.read_u32()?;
+                let length = self.read_count()?;
+                // arrays such as the tokenizer's vocabulary are large and never needed, they are skipped over
+                self.skip_array(item_type, length, 1)?;
+                Value::Other
+            }
+            _ => {
+                return Err(invalid_data(&format!(
+                    "unknown metadata value type {}",
+                    value_type
+                )))
+            }
+        })
+    }
+}
+
+impl GgufInfo {
+    pub fn from_file<P: AsRef<Path>>(filepath: P) -> io::Result<Self> {
+        let file = fs::File::open(filepath)?;
+        let file_size = file.metadata()?.len();
+        Self::from_reader(file, file_size)
+    }
+    fn from_reader<R: Read + Seek>(reader: R, file_size: u64) -> io::Result<Self> {
+        let mut reader = GgufReader {
+            reader: BufReader::new(reader),
+            version: 0,
+        };
+        if &reader.read_bytes::<4>()? != b"GGUF" {
+            return Err(invalid_data("not a GGUF file"));
+        }
+        reader.version = reader.read_u32()?;
+
+        let mut info = Self {
+            version: reader.version,
+            file_size,
+            tensor_count: reader.read_count()?,
+            metadata_count: reader.read_count()?,
+            ..Default::default()
+        };
+        let mut context_lengths: Vec<(String, u64)> = Vec::new();
+        for _ in 0..info.metadata_count {
+            let key = reader.read_string()?;
+            let value_type = reader.read_u32()?;
+            match (key.as_str(), reader.read_value(value_type)?) {
+                ("general.name", Value::String(name)) => info.name = Some(name),
+                ("general.architecture", Value::String(architecture)) => {
+                    info.architecture = Some(architecture)
+                }
+                ("general.parameter_count", Value::Unsigned(count)) => {
+                    info.parameter_count = Some(count)
+                }
+                ("general.file_type", Value::Unsigned(file_type)) => {
+                    info.file_type = Some(file_type as u32)
+                }
+                ("tokenizer.chat_template", Value::String(template)) => {
+                    info.chat_template = Some(template)
+                }
+                (key, Value::Unsigned(length)) if key.ends_with(".context_length") => {
+                    context_lengths.push((key.to_string(), length))
+                }
+                (key, Value::Signed(length)) if key.ends_with(".context_length") => {
+                    context_lengths.push((key.to_string(), length.max(0) as u64))
+                }
+                _ => (),
+            }
+        }
+        info.context_length = context_lengths
+            .iter()
+            .find(|(key, _)| {
+                info.architecture
+                    .as_ref()
+                    .is_some_and(|architecture| *key == format!("{}.context_length", architecture))
+            })
+            .or(context_lengths.first())
+            .map(|(_, length)| *length);
+
+        // older files don't record the parameter count, it's summed up from the tensor descriptions instead
+        if info.parameter_count.is_none() {
+            let mut parameter_count: u64 = 0;
+            for _ in 0..info.tensor_count {
+                reader.read_string()?;
+                let dimensions = reader.read_u32()?;
+                let mut elements: u64 = 1;
+                for _ in 0..dimensions {
+                    elements = elements.saturating_mul(reader.read_count()?);
+                }
+                // tensor type and data offset
+                reader.skip(12)?;
+                parameter_count = parameter_count.saturating_add(elements);
+            }
+            info.parameter_count = Some(parameter_count);
+        }
+        Ok(info)
+    }
+    /// the quantization used for most of the model's tensors
+    pub fn quantization(&self) -> &'static str {
+        match self.file_type {
+            Some(0) => "F32",
+            Some(1) => "F16",
+            Some(2) => "Q4_0",
+            Some(3) => "Q4_1",
+            Some(4) => "Q4_1_SOME_F16",
+            Some(7) => "Q8_0",
+            Some(8) => "Q5_0",
+            Some(9) => "Q5_1",
+            Some(10) => "Q2_K",
+            Some(11) => "Q3_K_S",
+            Some(12) => "Q3_K_M",
+            Some(13) => "Q3_K_L",
+            Some(14) => "Q4_K_S",
+            Some(15) => "Q4_K_M",
+            Some(16) => "Q5_K_S",
+            Some(17) => "Q5_K_M",
+            Some(18) => "Q6_K",
+            Some(19) => "IQ2_XXS",
+            Some(20) => "IQ2_XS",
+            Some(21) => "Q2_K_S",
+            Some(22) => "IQ3_XS",
+            Some(23) => "IQ3_XXS",
+            Some(24) => "IQ1_S",
+            Some(25) => "IQ4_NL",
+            Some(26) => "IQ3_S",
+            Some(27) => "IQ3_M",
+            Some(28) => "IQ2_S",
+            Some(29) => "IQ2_M",
+            Some(30) => "IQ4_XS",
+            Some(31) => "IQ1_M",
+            Some(32) => "BF16",
+            _ => "unknown",
+        }
+    }
+    pub fn to_print(&self) -> Vec<String> {
+        vec![
+            format!(
+                "name                         :    '{}'",
+                self.name.as_deref().unwrap_or("?")
+            ),
+            format!(
+                "architecture                 :    '{}'",
+                self.architecture.as_deref().unwrap_or("?")
+            ),
+            format!(
+                "parameters                   :    '{}'",
+                self.parameter_count
+                    .map(format_count)
+                    .unwrap_or("?".to_string())
+            ),
+            format!(
+                "quantization                 :    '{}'",
+                self.quantization()
+            ),
+            format!(
+                "trained context length       :    '{}'",
+                self.context_length
+                    .map(|length| length.to_string())
+                    .unwrap_or("?".to_string())
+            ),
+            format!(
+                "chat template                :    '{}'",
+                if self.chat_template.is_some() {
+                    "embedded"
+                } else {
+                    "none"
+                }
+            ),
+        ]
+    }
+    /// everything known about the file, used by the model information tab
+    pub fn to_print_detailed(&self) -> Vec<String> {
+        let mut lines = self.to_print();
+        lines.push(format!(
+            "file size                    :    '{:.2} GiB'",
+            self.file_size as f64 / 1024_f64.powi(3)
+        ));
+        lines.push(format!(
+            "GGUF version                 :    '{}'",
+            self.version
+        ));
+        lines.push(format!(
+            "tensors                      :    '{}'",
+            self.tensor_count
+        ));
+        lines.push(format!(
+            "metadata entries             :    '{}'",
+            self.metadata_count
+        ));
+        lines
+    }
+}
+
+/// i.e. 7241732096 => 7.24B
+fn format_count(count: u64) -> String {
+    match count {
+        0..=999_999 => count.to_string(),
+        1_000_000..=999_999_999 => format!("{:.2}M", count as f64 / 1e6),
+        _ => format!("{:.2}B", count as f64 / 1e9),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn string(text: &str) -> Vec<u8> {
+        let mut bytes = (text.len() as u64).to_le_bytes().to_vec();
+        bytes.extend(text.as_bytes());
+        bytes
+    }
+
+    fn entry(key: &str, value_type: u32, value: &[u8]) -> Vec<u8> {
+        let mut bytes = string(key);
+        bytes.extend(value_type.to_le_bytes());
+        bytes.extend(value);
+        bytes
+    }
+
+    /// a version 3 header with the given metadata entries and tensor descriptions
+    fn header(tensor_count: u64, entries: &[Vec<u8>], tensors: &[u8]) -> Vec<u8> {
+        let mut bytes = b"GGUF".to_vec();
+        bytes.extend(3_u32.to_le_bytes());
+        bytes.extend(tensor_count.to_le_bytes());
+        bytes.extend((entries.len() as u64).to_le_bytes());
+        entries.iter().for_each(|entry| bytes.extend(entry));
+        bytes.extend(tensors);
+        bytes
+    }
+
+    fn read(bytes: &[u8]) -> io::Result<GgufInfo> {
+        GgufInfo::from_reader(Cursor::new(bytes), bytes.len() as u64)
+    }
+
+    fn minimal() -> Vec<u8> {
+        let mut vocabulary = STRING.to_le_bytes().to_vec();
+        vocabulary.extend(2_u64.to_le_bytes());
+        vocabulary.extend(string("<s>"));
+        vocabulary.extend(string("hello"));
+        let mut scores = FLOAT32.to_le_bytes().to_vec();
+        scores.extend(2_u64.to_le_bytes());
+        scores.extend([0; 8]);
+        // a single 3 x 4 tensor, then its type and data offset
+        let mut tensors = string("output.weight");
+        tensors.extend(2_u32.to_le_bytes());
+        tensors.extend(3_u64.to_le_bytes());
+        tensors.extend(4_u64.to_le_bytes());
+        tensors.extend([0; 12]);
+        header(
+            1,
+            &[
+                entry("general.architecture", STRING, &string("llama")),
+                entry("general.name", STRING, &string("tiny")),
+                entry("general.file_type", UINT32, &15_u32.to_le_bytes()),
+                entry("tokenizer.ggml.tokens", ARRAY, &vocabulary),
+                entry("tokenizer.ggml.scores", ARRAY, &scores),
+                entry("llama.context_length", UINT32, &4096_u32.to_le_bytes()),
+                entry("tokenizer.chat_template", STRING, &string("{{ messages }}")),
+            ],
+            &tensors,
+        )
+    }
+
+    #[test]
+    fn reads_a_minimal_header() {
+        let info = read(&minimal()).unwrap();
+        assert_eq!(info.version, 3);
+        assert_eq!(info.tensor_count, 1);
+        assert_eq!(info.metadata_count, 7);
+        assert_eq!(info.name.as_deref(), Some("tiny"));
+        assert_eq!(info.architecture.as_deref(), Some("llama"));
+        assert_eq!(info.quantization(), "Q4_K_M");
+        assert_eq!(info.context_length, Some(4096));
+        assert_eq!(info.chat_template.as_deref(), Some("{{ messages }}"));
+        assert_eq!(info.parameter_count, Some(12));
+    }
+
+    #[test]
+    fn fails_on_a_truncated_header() {
+        let bytes = minimal();
+        let error = read(&bytes[..bytes.len() / 2]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn fails_on_lengths_that_overflow() {
+        let mut array = UINT64.to_le_bytes().to_vec();
+        array.extend(u64::MAX.to_le_bytes());
+        let error = read(&header(0, &[entry("huge", ARRAY, &array)], &[])).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut array = UINT8.to_le_bytes().to_vec();
+        array.extend(u64::MAX.to_le_bytes());
+        let error = read(&header(0, &[entry("huge", ARRAY, &array)], &[])).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // an array of arrays of arrays..., each holding one
+        let mut array = Vec::new();
+        for _ in 0..64 {
+            array.extend(ARRAY.to_le_bytes());
+            array.extend(1_u64.to_le_bytes());
+        }
+        array.extend(UINT8.to_le_bytes());
+        array.extend(0_u64.to_le_bytes());
+        let error = read(&header(0, &[entry("deep", ARRAY, &array)], &[])).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut name = u64::MAX.to_le_bytes().to_vec();
+        name.extend(b"tiny");
+        let error = read(&header(0, &[entry("general.name", STRING, &name)], &[])).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{
+    backend::{self, GenerationEvent, Prompt, StopReason},
+    gguf::GgufInfo,
+    setup::{
+        find_model, load_app_configuration, load_default_llama_configuration,
+        load_gguf_models_with_config, load_rules, set_quiet, set_read_only,
+    },
+};
+use std::{
+    io::{self, IsTerminal, Read, Write},
+    time::{Duration, Instant},
+};
+
+// exit codes, anything the command line or the setup gets wrong is a usage error
+const FAILURE: i32 = 1;
+const USAGE_ERROR: i32 = 2;
+
+/// answers a single prompt without the terminal user interface and returns the exit code
+/// the reply is streamed to stdout, everything else goes to stderr
+/// the prompt is read from stdin when none is given, no file is written, not even to update an outdated config
+pub fn ask(model: Option<&str>, words: &[String]) -> i32 {
+    let prompt = if words.is_empty() {
+        if io::stdin().is_terminal() {
+            eprintln!("error: no prompt given, pass it as an argument or on stdin");
+            return USAGE_ERROR;
+        }
+        let mut prompt = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut prompt) {
+            eprintln!("error: failed to read the prompt from stdin, {}", error);
+            return USAGE_ERROR;
+        }
+        prompt
+    } else {
+        words.join(" ")
+    };
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        eprintln!("error: the prompt is empty");
+        return USAGE_ERROR;
+    }
+
+    set_quiet(true);
+    set_read_only(true);
+    let app_config = load_app_configuration();
+    let defaults = load_default_llama_configuration();
+    let rules = load_rules();
+    let models = match load_gguf_models_with_config(&defaults, &rules) {
+        Ok(models) => models,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            return USAGE_ERROR;
+        }
+    };
+    let index = match model {
+        Some(name) => match find_model(&models, name) {
+            Some(index) => index,
+            None => {
+                eprintln!(
+                    "error: no model's file name contains \"{}\", found {}",
+                    name,
+                    model_names(&models)
+                );
+                return USAGE_ERROR;
+            }
+        },
+        None if models.len() == 1 => 0,
+        None => {
+            eprintln!(
+                "error: several models were found, choose one with --model: {}",
+                model_names(&models)
+            );
+            return USAGE_ERROR;
+        }
+    };
+    let (model, layers) = &models[index];
+    let config = layers.resolve();
+
+    // laid out the same way as in the chat tab, without any past exchange
+    let info = GgufInfo::from_file(model).ok();
+    let embedded = info.as_ref().and_then(|info| info.chat_template.as_deref());
+    let prompt = Prompt::new(
+        &config.chat_template(embedded),
+        &config.system_prompt,
+        [],
+        prompt,
+    );
+
+    let timeout = Duration::from_secs_f64(app_config.timeout);
+    let mut backend = backend::from_config(&config);
+    let mut generation = match backend.generate(model, &config, &prompt, timeout) {
+        Ok(generation) => generation,
+        Err(error) => {
+            eprintln!("error: failed to start the generation, {}", error);
+            return FAILURE;
+        }
+    };
+    let deadline = Instant::now() + timeout;
+    let mut stdout = io::stdout().lock();
+    let mut ends_with_newline = true;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match generation.wait_next(remaining) {
+            Some(GenerationEvent::Text(text)) => {
+                if text.is_empty() {
+                    continue;
+                }
+                ends_with_newline = text.ends_with('\n');
+                // a closed stdout, i.e. piped into 'head', ends the generation
+                if stdout
+                    .write_all(text.as_bytes())
+                    .and_then(|()| stdout.flush())
+                    .is_err()
+                {
+                    generation.stop();
+                    return FAILURE;
+                }
+            }
+            Some(GenerationEvent::Status(_)) | Some(GenerationEvent::Timings(_)) => (),
+            Some(GenerationEvent::Warning(warning)) => eprintln!("warning: {}", warning),
+            Some(GenerationEvent::Finished(reason)) => {
+                if !ends_with_newline {
+                    let _ = writeln!(stdout);
+                }
+                if reason == StopReason::TokenLimit {
+                    eprintln!("warning: the reply was cut short by the tokens to predict");
+                }
+                return 0;
+            }
+            Some(GenerationEvent::Failed(error)) => {
+                if !ends_with_newline {
+                    let _ = writeln!(stdout);
+                }
+                eprintln!("error: {}", error);
+                return FAILURE;
+            }
+            None => {
+                generation.stop();
+                if !ends_with_newline {
+                    let _ = writeln!(stdout);
+                }
+                eprintln!(
+                    "error: the generation timed out after {} seconds",
+                    app_config.timeout
+                );
+                return FAILURE;
+            }
+        }
+    }
+}
+
+fn model_names(models: &[(std::path::PathBuf, crate::layers::ConfigLayers)]) -> String {
+    models
+        .iter()
+        .map(|(model, _)| model.file_name().unwrap_or("?".as_ref()).to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,268 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::Span,
+};
+
+/// what's needed to color a language's code, kept simple, only the most common tokens are told apart
+struct Syntax {
+    // the names a code block can be given for the language, in lowercase and separated by spaces
+    names: &'static str,
+    // separated by spaces
+    keywords: &'static str,
+    // whether keywords have to match case, i.e. not for sql
+    case_sensitive: bool,
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const SYNTAXES: [Syntax; 11] = [
+    Syntax {
+        names: "rust rs",
+        keywords: "as async await break const continue crate dyn else enum extern false fn for if \
+            impl in let loop match mod move mut pub ref return self Self static struct \
+            super trait true type unsafe use where while",
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+    },
+    Syntax {
+        names: "python py",
+        keywords: "and as assert async await break class continue def del elif else except False \
+            finally for from global if import in is lambda None nonlocal not or pass raise \
+            return self True try while with yield",
+        case_sensitive: true,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        names: "javascript js jsx typescript ts tsx mjs node",
+        keywords: "async await break case catch class const continue default delete do else enum \
+            export extends false finally for function if implements import in instanceof \
+            interface let new null of return super switch this throw true try type typeof \
+            undefined var void while yield",
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Syntax {
+        names: "c h cpp c++ cc hpp cxx",
+        keywords: "auto bool break case char class const continue default delete do double else \
+            enum extern false float for goto if inline int long namespace new nullptr \
+            private protected public return short signed sizeof static struct switch \
+            template this true typedef typename union unsigned using virtual void volatile \
+            while",
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        names: "java kotlin kt csharp cs c#",
+        keywords: "abstract boolean break byte case catch char class const continue default do \
+            double else enum extends false final finally float for fun if implements \
+            import instanceof int interface long namespace new null override package \
+            private protected public return short static string super switch this throw \
+            throws true try using val var void while",
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        names: "go golang",
+        keywords: "break case chan const continue default defer else fallthrough false for func \
+            go goto if import interface map nil package range return select struct switch \
+            true type var",
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+    },
+    Syntax {
+        names: "bash sh shell zsh console fish powershell ps1",
+        keywords: "case do done echo elif else esac exit export fi for function if in local \
+            return then while",
+        case_sensitive: true,
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        names: "json jsonc",
+        keywords: "true false null",
+        case_sensitive: true,
+        line_comments: &["//"],
+        block_comment: None,
+        quotes: &['"'],
+    },
+    Syntax {
+        names: "toml yaml yml ini conf cfg",
+        keywords: "true false null",
+        case_sensitive: true,
+        line_comments: &["#", ";"],
+        block_comment: None,
+        quotes: &['"', '\''],
+    },
+    Syntax {
+        names: "sql sqlite postgres mysql",
+        keywords: "add alter and as asc by case create delete desc distinct drop else end exists \
+            from group having in index inner insert into is join key left like limit not \
+            null on or order outer primary right select set table then union update values \
+            when where",
+        case_sensitive: false,
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['\'', '"'],
+    },
+    Syntax {
+        names: "lua",
+        keywords: "and break do else elseif end false for function goto if in local nil not or \
+            repeat return then true until while",
+        case_sensitive: true,
+        line_comments: &["--"],
+        block_comment: Some(("--[[", "]]")),
+        quotes: &['"', '\''],
+    },
+];
+
+const CODE: Style = Style::new().fg(Color::Gray);
+const KEYWORD: Style = Style::new().fg(Color::Magenta);
+const TYPE: Style = Style::new().fg(Color::Cyan);
+const STRING: Style = Style::new().fg(Color::Yellow);
+const NUMBER: Style = Style::new().fg(Color::LightRed);
+const COMMENT: Style = Style::new()
+    .fg(Color::DarkGray)
+    .add_modifier(Modifier::ITALIC);
+
+fn is_identifier(chr: char) -> bool {
+    chr.is_alphanumeric() || chr == '_'
+}
+
+/// colors a code block line by line, unknown languages are left plain
+pub struct Highlighter {
+    syntax: Option<&'static Syntax>,
+    // whether the previous line ended inside a block comment
+    in_comment: bool,
+}
+
+impl Highlighter {
+    /// the language as given after the code fence, i.e. 'rust' or 'py'
+    pub fn new(language: &str) -> Self {
+        let language = language.to_lowercase();
+        Self {
+            syntax: SYNTAXES
+                .iter()
+                .find(|syntax| syntax.names.split(' ').any(|name| name == language)),
+            in_comment: false,
+        }
+    }
+    pub fn line(&mut self, line: &str) -> Vec<Span<'static>> {
+        let syntax = match self.syntax {
+            Some(syntax) => syntax,
+            None => return vec![Span::styled(line.to_string(), CODE)],
+        };
+        let mut spans: Vec<Span<'static>> = Vec::new();
+        let mut push = |text: &str, style: Style| {
+            if !text.is_empty() {
+                spans.push(Span::styled(text.to_string(), style));
+            }
+        };
+        let mut rest = line;
+        // text that isn't a token of its own, pushed in one go once a token is found
+        let mut plain = String::new();
+        while !rest.is_empty() {
+            if self.in_comment {
+                let (_, end) = syntax.block_comment.unwrap_or_default();
+                let length = match rest.find(end) {
+                    Some(index) => {
+                        self.in_comment = false;
+                        index + end.len()
+                    }
+                    None => rest.len(),
+                };
+                push(&rest[..length], COMMENT);
+                rest = &rest[length..];
+                continue;
+            }
+            if let Some((start, _)) = syntax
+                .block_comment
+                .filter(|(start, _)| rest.starts_with(start))
+            {
+                push(&plain, CODE);
+                plain.clear();
+                push(start, COMMENT);
+                rest = &rest[start.len()..];
+                self.in_comment = true;
+                continue;
+            }
+            if syntax
+                .line_comments
+                .iter()
+                .any(|comment| rest.starts_with(comment))
+            {
+                push(&plain, CODE);
+                plain.clear();
+                push(rest, COMMENT);
+                break;
+            }
+            let chr = rest.chars().next().unwrap();
+            let length = if syntax.quotes.contains(&chr) {
+                // up to the closing quote that isn't escaped, or the end of the line
+                let mut escaped = false;
+                let end = rest[chr.len_utf8()..]
+                    .char_indices()
+                    .find(|(_, next)| {
+                        let closes = *next == chr && !escaped;
+                        escaped = *next == '\\' && !escaped;
+                        closes
+                    })
+                    .map_or(rest.len(), |(index, _)| chr.len_utf8() * 2 + index);
+                push(&plain, CODE);
+                plain.clear();
+                push(&rest[..end], STRING);
+                end
+            } else if is_identifier(chr) && !plain.ends_with(is_identifier) {
+                let end = rest
+                    .find(|next: char| {
+                        !(is_identifier(next) || chr.is_ascii_digit() && next == '.')
+                    })
+                    .unwrap_or(rest.len());
+                let word = &rest[..end];
+                let style = if chr.is_ascii_digit() {
+                    Some(NUMBER)
+                } else if syntax.keywords.split(' ').any(|keyword| {
+                    if syntax.case_sensitive {
+                        keyword == word
+                    } else {
+                        keyword.eq_ignore_ascii_case(word)
+                    }
+                }) {
+                    Some(KEYWORD)
+                } else if chr.is_uppercase() {
+                    Some(TYPE)
+                } else {
+                    None
+                };
+                match style {
+                    Some(style) => {
+                        push(&plain, CODE);
+                        plain.clear();
+                        push(word, style);
+                    }
+                    None => plain.push_str(word),
+                }
+                end
+            } else {
+                plain.push(chr);
+                chr.len_utf8()
+            };
+            rest = &rest[length..];
+        }
+        push(&plain, CODE);
+        spans
+    }
+}
@@ -0,0 +1,244 @@
+use crate::editor::Editor;
+
+/// the inputs sent in a session, oldest first, which can be brought back into the editor
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    // the entry shown in the editor while going through the history
+    browsing: Option<usize>,
+    // what was in the editor before going through the history, given back when leaving it
+    draft: String,
+    // the reverse search being typed in, if any
+    search: Option<Search>,
+}
+
+/// a reverse incremental search through the history, like ctrl + r in a shell
+#[derive(Clone, Debug, Default)]
+pub struct Search {
+    pub query: String,
+    // the entry matching the query, none if none does
+    pub found: Option<usize>,
+}
+
+impl History {
+    pub fn new(entries: Vec<String>) -> Self {
+        Self {
+            entries,
+            ..Self::default()
+        }
+    }
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+    /// records a sent input, blank ones and repeats of the latest entry are left out
+    pub fn push(&mut self, input: &str) {
+        self.browsing = None;
+        self.search = None;
+        if !input.trim().is_empty() && self.entries.last().is_none_or(|last| last != input) {
+            self.entries.push(input.to_string());
+        }
+    }
+    /// shows the entry before the one shown, the latest one if none is
+    pub fn previous(&mut self, editor: &mut Editor) {
+        self.search = None;
+        let index = match self.browsing {
+            None if self.entries.is_empty() => return,
+            None => {
+                self.draft = editor.text().to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => return,
+            Some(index) => index - 1,
+        };
+        self.browsing = Some(index);
+        editor.set_text(&self.entries[index]);
+    }
+    /// shows the entry after the one shown, what was typed before going through the history after the latest one
+    pub fn next(&mut self, editor: &mut Editor) {
+        self.search = None;
+        match self.browsing {
+            None => (),
+            Some(index) if index + 1 < self.entries.len() => {
+                self.browsing = Some(index + 1);
+                editor.set_text(&self.entries[index + 1]);
+            }
+            Some(_) => {
+                self.browsing = None;
+                editor.set_text(&self.draft);
+            }
+        }
+    }
+    pub fn search(&self) -> Option<&Search> {
+        self.search.as_ref()
+    }
+    /// starts a search, or looks for an older entry matching the query if one is underway
+    pub fn search_older(&mut self, editor: &mut Editor) {
+        match self.search.as_ref() {
+            None => {
+                if self.browsing.is_none() {
+                    self.draft = editor.text().to_string();
+                }
+                self.search = Some(Search::default());
+            }
+            Some(search) => {
+                let before = search.found.unwrap_or(self.entries.len());
+                if let Some(index) = self.find(&search.query, before) {
+                    self.show_found(index, editor);
+                }
+            }
+        }
+    }
+    pub fn search_push(&mut self, chr: char, editor: &mut Editor) {
+        if let Some(search) = self.search.as_mut() {
+            search.query.push(chr);
+            // the entry found so far is kept if it still matches
+            let before = search.found.map_or(self.entries.len(), |index| index + 1);
+            let query = search.query.clone();
+            match self.find(&query, before) {
+                Some(index) => self.show_found(index, editor),
+                None => self.search.as_mut().unwrap().found = None,
+            }
+        }
+    }
+    pub fn search_pop(&mut self, editor: &mut Editor) {
+        if let Some(search) = self.search.as_mut() {
+            search.query.pop();
+            let query = search.query.clone();
+            match self.find(&query, self.entries.len()) {
+                Some(index) if !query.is_empty() => self.show_found(index, editor),
+                _ => {
+                    self.search.as_mut().unwrap().found = None;
+                    editor.set_text(&self.draft);
+                }
+            }
+        }
+    }
+    /// ends the search, leaving the entry found in the editor
+    pub fn accept_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.browsing = search.found.or(self.browsing);
+        }
+    }
+    /// ends the search, giving back what was typed before it
+    pub fn cancel_search(&mut self, editor: &mut Editor) {
+        if self.search.take().is_some() {
+            self.browsing = None;
+            editor.set_text(&self.draft);
+        }
+    }
+    /// the latest entry before the given index containing the query, case is ignored
+    fn find(&self, query: &str, before: usize) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+        let query = query.to_lowercase();
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.to_lowercase().contains(&query))
+    }
+    fn show_found(&mut self, index: usize, editor: &mut Editor) {
+        if let Some(search) = self.search.as_mut() {
+            search.found = Some(index);
+        }
+        editor.set_text(&self.entries[index]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> History {
+        History::new(vec![
+            String::from("Tell me a joke"),
+            String::from("explain rust lifetimes"),
+            String::from("another JOKE please"),
+        ])
+    }
+
+    #[test]
+    fn stops_at_both_ends() {
+        let mut history = history();
+        let mut editor = Editor::default();
+        history.previous(&mut editor);
+        assert_eq!(editor.text(), "another JOKE please");
+        history.previous(&mut editor);
+        history.previous(&mut editor);
+        history.previous(&mut editor);
+        assert_eq!(editor.text(), "Tell me a joke");
+
+        history.next(&mut editor);
+        assert_eq!(editor.text(), "explain rust lifetimes");
+        history.next(&mut editor);
+        history.next(&mut editor);
+        history.next(&mut editor);
+        assert_eq!(editor.text(), "");
+
+        let mut empty = History::default();
+        empty.previous(&mut editor);
+        empty.next(&mut editor);
+        assert_eq!(editor.text(), "");
+    }
+
+    #[test]
+    fn gives_back_the_draft() {
+        let mut history = history();
+        let mut editor = Editor::default();
+        editor.set_text("half typed");
+        history.previous(&mut editor);
+        history.previous(&mut editor);
+        assert_eq!(editor.text(), "explain rust lifetimes");
+        history.next(&mut editor);
+        history.next(&mut editor);
+        assert_eq!(editor.text(), "half typed");
+
+        history.search_older(&mut editor);
+        for chr in "rust".chars() {
+            history.search_push(chr, &mut editor);
+        }
+        assert_eq!(editor.text(), "explain rust lifetimes");
+        history.cancel_search(&mut editor);
+        assert_eq!(editor.text(), "half typed");
+        assert!(history.search().is_none());
+    }
+
+    #[test]
+    fn records_sent_inputs_once() {
+        let mut history = History::default();
+        history.push("hello");
+        history.push("hello");
+        history.push("  ");
+        history.push("bye");
+        assert_eq!(history.entries(), ["hello", "bye"]);
+    }
+
+    #[test]
+    fn searches_from_the_latest_entry() {
+        let mut history = history();
+        let mut editor = Editor::default();
+        history.search_older(&mut editor);
+        for chr in "joke".chars() {
+            history.search_push(chr, &mut editor);
+        }
+        assert_eq!(history.search().unwrap().found, Some(2));
+        assert_eq!(editor.text(), "another JOKE please");
+
+        history.search_older(&mut editor);
+        assert_eq!(history.search().unwrap().found, Some(0));
+        assert_eq!(editor.text(), "Tell me a joke");
+        // no older entry matches, the one found is kept
+        history.search_older(&mut editor);
+        assert_eq!(history.search().unwrap().found, Some(0));
+
+        history.search_push('s', &mut editor);
+        assert_eq!(history.search().unwrap().found, None);
+        history.search_pop(&mut editor);
+        assert_eq!(history.search().unwrap().found, Some(2));
+
+        history.accept_search();
+        assert!(history.search().is_none());
+        assert_eq!(editor.text(), "another JOKE please");
+        history.previous(&mut editor);
+        assert_eq!(editor.text(), "explain rust lifetimes");
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{backend::Timings, conversation::ConversationChunk, paths};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// [DATA DIRECTORY]/imports
+pub fn directory() -> PathBuf {
+    paths::get().data.join("imports")
+}
+
+/// a conversation read from another application's export, not yet stored as a session
+pub struct ImportedConversation {
+    pub name: String,
+    // seconds since the unix epoch, if the export recorded it
+    pub created: Option<u64>,
+    pub system_prompt: Option<String>,
+    pub chunks: Vec<ConversationChunk>,
+}
+
+/// the JSON files waiting in the imports directory, files that were already imported end with '.imported' instead
+pub fn pending_files() -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = match fs::read_dir(directory()) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    files.sort();
+    files
+}
+
+/// reads every conversation a file contains, understands the following layouts
+/// - a list of role/content messages
+/// - an object holding such a list under 'messages', i.e. Sulmo's own exports or OpenAI requests
+/// - ChatGPT's 'conversations.json' export, a list of conversations made of a tree of messages
+/// - a list of any of the above
+pub fn read_file(filepath: &Path) -> Result<Vec<ImportedConversation>, String> {
+    let buffer = fs::read(filepath).map_err(|error| error.to_string())?;
+    let value: Value = serde_json::from_slice(&buffer).map_err(|error| error.to_string())?;
+    let stem = filepath
+        .file_stem()
+        .unwrap_or("?".as_ref())
+        .to_string_lossy()
+        .to_string();
+
+    let conversations: Vec<&Value> = match &value {
+        Value::Array(items) if items.iter().all(is_conversation) && !items.is_empty() => {
+            items.iter().collect()
+        }
+        value => vec![value],
+    };
+    let several = conversations.len() > 1;
+    let mut imported = Vec::new();
+    for (index, conversation) in conversations.into_iter().enumerate() {
+        let default_name = if several {
+            format!("{} {}", stem, index + 1)
+        } else {
+            stem.clone()
+        };
+        if let Some(conversation) = read_conversation(conversation, default_name) {
+            imported.push(conversation);
+        }
+    }
+    if imported.is_empty() {
+        Err("no conversation found".to_string())
+    } else {
+        Ok(imported)
+    }
+}
+
+/// marks a file as imported so that it isn't imported twice
+pub fn mark_imported(filepath: &Path) -> std::io::Result<()> {
+    let mut imported = filepath.as_os_str().to_owned();
+    imported.push(".imported");
+    fs::rename(filepath, imported)
+}
+
+fn is_conversation(value: &Value) -> bool {
+    value.get("messages").is_some_and(Value::is_array) || value.get("mapping").is_some()
+}
+
+fn read_conversation(value: &Value, default_name: String) -> Option<ImportedConversation> {
+    let name = ["title", "session", "name"]
+        .iter()
+        .find_map(|key| value.get(key).and_then(Value::as_str))
+        .filter(|name| !name.trim().is_empty())
+        .map(str::to_string)
+        .unwrap_or(default_name);
+    let created = ["create_time", "created", "created_at"]
+        .iter()
+        .find_map(|key| value.get(key).and_then(Value::as_f64))
+        .map(|seconds| seconds as u64);
+
+    let messages: Vec<&Value> = if let Some(mapping) = value.get("mapping") {
+        walk_mapping(mapping, value.get("current_node").and_then(Value::as_str))
+    } else if let Some(Value::Array(messages)) = value.get("messages") {
+        messages.iter().collect()
+    } else if let Value::Array(messages) = value {
+        messages.iter().collect()
+    } else {
+        return None;
+    };
+
+    let (system_prompt, chunks) = to_chunks(&messages);
+    if chunks.is_empty() {
+        return None;
+    }
+    Some(ImportedConversation {
+        name,
+        created,
+        system_prompt,
+        chunks,
+    })
+}
+
+/// ChatGPT stores messages as a tree, the conversation is the branch leading to the current node
+fn walk_mapping<'a>(mapping: &'a Value, current_node: Option<&str>) -> Vec<&'a Value> {
+    let Some(nodes) = mapping.as_object() else {
+        return Vec::new();
+    };
+    // without a current node, the branch ending with the latest leaf is followed
+    let mut node_id = current_node.map(str::to_string).or_else(|| {
+        nodes
+            .iter()
+            .filter(|(_, node)| {
+                node.get("children")
+                    .and_then(Value::as_array)
+                    .is_none_or(|children| children.is_empty())
+            })
+            .max_by(|a, b| {
+                let time = |node: &Value| {
+                    node.pointer("/message/create_time")
+                        .and_then(Value::as_f64)
+                        .unwrap_or(0.0)
+                };
+                time(a.1).total_cmp(&time(b.1))
+            })
+            .map(|(id, _)| id.clone())
+    });
+    let mut branch = Vec::new();
+    while let Some(node) = node_id.as_ref().and_then(|id| nodes.get(id)) {
+        if let Some(message) = node.get("message").filter(|message| !message.is_null()) {
+            branch.push(message);
+        }
+        node_id = node
+            .get("parent")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        if branch.len() > nodes.len() {
+            break;
+        }
+    }
+    branch.reverse();
+    branch
+}
+
+/// pairs the user's messages with the replies that follow them, leading system messages become the system prompt
+/// and replies coming before any user message are left out
+fn to_chunks(messages: &[&Value]) -> (Option<String>, Vec<ConversationChunk>) {
+    let mut system_prompt: Option<String> = None;
+    let mut chunks: Vec<ConversationChunk> = Vec::new();
+    // the exchange being assembled, its reply isn't complete until the next user message
+    let mut input = String::new();
+    let mut output = String::new();
+    let mut error = String::new();
+    let mut timings: Option<Timings> = None;
+    let mut answered = false;
+
+    for message in messages {
+        let role = message
+            .get("role")
+            .or_else(|| message.pointer("/author/role"))
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        let content = content_text(message.get("content"));
+        match role {
+            "system" | "developer"
+                if chunks.is_empty() && input.is_empty() && !content.trim().is_empty() =>
+            {
+                let prompt = system_prompt.get_or_insert_with(String::new);
+                if !prompt.is_empty() {
+                    prompt.push_str("\n\n");
+                }
+                prompt.push_str(&content);
+            }
+            "user" | "human" => {
+                if content.trim().is_empty() {
+                    continue;
+                }
+                if answered {
+                    chunks.push(ConversationChunk::from_exchange(
+                        std::mem::take(&mut input),
+                        std::mem::take(&mut output),
+                        std::mem::take(&mut error),
+                        timings.take(),
+                    ));
+                    answered = false;
+                }
+                if !input.is_empty() {
+                    input.push_str("\n\n");
+                }
+                input.push_str(&content);
+            }
+            "assistant" | "model" | "bot" => {
+                // a greeting before the user said anything has no input to pair with
+                if input.is_empty() || (content.is_empty() && message.get("error").is_none()) {
+                    continue;
+                }
+                if !output.is_empty() && !content.is_empty() {
+                    output.push_str("\n\n");
+                }
+                output.push_str(&content);
+                if let Some(message_error) = message.get("error").and_then(Value::as_str) {
+                    error = message_error.to_string();
+                }
+                if let Some(message_timings) = message.get("timings") {
+                    timings = serde_json::from_value(message_timings.clone()).ok();
+                }
+                answered = true;
+            }
+            // tool calls and other roles have no place in a chat with a local model
+            _ => (),
+        }
+    }
+    if !input.is_empty() {
+        chunks.push(ConversationChunk::from_exchange(
+            input, output, error, timings,
+        ));
+    }
+    (system_prompt, chunks)
+}
+
+/// content is either plain text, a list of parts (OpenAI) or an object holding parts (ChatGPT)
+fn content_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| match part {
+                Value::String(text) => Some(text.as_str()),
+                part => part.get("text").and_then(Value::as_str),
+            })
+            .collect::<Vec<&str>>()
+            .join("\n"),
+        Some(Value::Object(object)) => match object.get("parts") {
+            Some(parts) => content_text(Some(parts)),
+            None => object
+                .get("text")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string(),
+        },
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    fn import(name: &str, contents: &str) -> Result<Vec<ImportedConversation>, String> {
+        mock::use_temporary_paths();
+        fs::create_dir_all(directory()).unwrap();
+        let filepath = directory().join(name);
+        fs::write(&filepath, contents).unwrap();
+        read_file(&filepath)
+    }
+
+    fn exchanges(conversation: &ImportedConversation) -> Vec<(&str, &str)> {
+        conversation
+            .chunks
+            .iter()
+            .map(|chunk| (chunk.get_input(false), chunk.get_output()))
+            .collect()
+    }
+
+    #[test]
+    fn follows_the_current_branch_of_a_chatgpt_export() {
+        let conversations = import(
+            "conversations.json",
+            r#"[{
+                "title": "Trip",
+                "create_time": 1700000000.5,
+                "current_node": "d",
+                "mapping": {
+                    "root": {"message": null, "parent": null, "children": ["a"]},
+                    "a": {
+                        "message": {"author": {"role": "system"}, "content": {"parts": ["Be brief."]}},
+                        "parent": "root", "children": ["b"]
+                    },
+                    "b": {
+                        "message": {"author": {"role": "user"}, "content": {"parts": ["Where to go?"]}},
+                        "parent": "a", "children": ["c", "x"]
+                    },
+                    "x": {
+                        "message": {"author": {"role": "assistant"}, "content": {"parts": ["Discarded."]}},
+                        "parent": "b", "children": []
+                    },
+                    "c": {
+                        "message": {"author": {"role": "assistant"}, "content": {"parts": ["Lisbon."]}},
+                        "parent": "b", "children": ["d"]
+                    },
+                    "d": {
+                        "message": {"author": {"role": "user"}, "content": {"parts": ["Why?"]}},
+                        "parent": "c", "children": []
+                    }
+                }
+            }]"#,
+        )
+        .unwrap();
+        assert_eq!(conversations.len(), 1);
+        let conversation = &conversations[0];
+        assert_eq!(conversation.name, "Trip");
+        assert_eq!(conversation.created, Some(1700000000));
+        assert_eq!(conversation.system_prompt.as_deref(), Some("Be brief."));
+        // the last question was never answered, it is kept without a reply
+        assert_eq!(
+            exchanges(conversation),
+            [("Where to go?", "Lisbon."), ("Why?", "")]
+        );
+    }
+
+    #[test]
+    fn leaves_out_replies_before_the_first_input() {
+        let conversations = import(
+            "greeting.json",
+            r#"{"messages": [
+                {"role": "assistant", "content": "Hello! How can I help?"},
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hi there."},
+                {"role": "user", "content": [{"type": "text", "text": "Bye"}]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(conversations[0].name, "greeting");
+        assert_eq!(
+            exchanges(&conversations[0]),
+            [("Hi", "Hi there."), ("Bye", "")]
+        );
+
+        let conversations = import(
+            "only-greeting.json",
+            r#"[{"role": "assistant", "content": "Hello!"}]"#,
+        );
+        assert_eq!(
+            conversations.err().as_deref(),
+            Some("no conversation found")
+        );
+    }
+
+    #[test]
+    fn names_the_conversations_of_a_list() {
+        let conversations = import(
+            "several.json",
+            r#"[
+                {"messages": [{"role": "user", "content": "one"}, {"role": "assistant", "content": "1"}]},
+                {"name": "second", "messages": [{"role": "user", "content": "two"}]}
+            ]"#,
+        )
+        .unwrap();
+        let names: Vec<&str> = conversations
+            .iter()
+            .map(|conversation| conversation.name.as_str())
+            .collect();
+        assert_eq!(names, ["several 1", "second"]);
+    }
+}
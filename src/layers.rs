@@ -0,0 +1,301 @@
+use crate::{
+    configs::{load_library, save_library, LoadError, ModelConfig, Versioned},
+    paths,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// where the value of a model's config field comes from, from the lowest to the highest priority
+#[derive(Clone, Debug, PartialEq)]
+pub enum Layer {
+    // Sulmo's own defaults
+    Builtin,
+    // ./configs/model.conf
+    Defaults,
+    // an entry of ./configs/rules.conf, identified by its pattern
+    Rule(String),
+    // ./configs/[NAME].conf
+    Model,
+}
+
+impl Layer {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Builtin => String::from("built-in"),
+            Self::Defaults => String::from("model.conf"),
+            Self::Rule(pattern) => format!("rule '{}'", pattern),
+            Self::Model => String::from("own config"),
+        }
+    }
+}
+
+/// an entry of ./configs/rules.conf, its fields are given to every model whose file name matches the pattern
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    // i.e. '*mistral*', '*' matches any text and '?' any single character, case is ignored
+    pub pattern: String,
+
+    // fields set for the matching models, named as in a config file
+    pub config: Map<String, Value>,
+}
+
+impl Rule {
+    /// [CONFIG DIRECTORY]/rules.conf
+    pub fn filepath() -> PathBuf {
+        paths::get().configs.join("rules.conf")
+    }
+    pub fn defaults() -> Vec<Self> {
+        vec![Self {
+            pattern: String::from("*.openai"),
+            config: Map::from_iter([(String::from("backend"), Value::from("openai"))]),
+        }]
+    }
+    pub fn library_from_file() -> Result<Vec<Self>, LoadError> {
+        load_library(&Self::filepath())
+    }
+    pub fn save_library(library: &[Self]) -> io::Result<()> {
+        save_library(&Self::filepath(), library)
+    }
+    pub fn matches(&self, file_name: &str) -> bool {
+        glob_match(&self.pattern, file_name)
+    }
+}
+
+/// '*' matches any text, '?' any single character, case is ignored
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut pattern_index, mut text_index) = (0, 0);
+    // where the last '*' is in the pattern and how much of the text it covers so far, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                star = Some((pattern_index, text_index));
+                pattern_index += 1;
+            }
+            Some(chr) if *chr == '?' || *chr == text[text_index] => {
+                pattern_index += 1;
+                text_index += 1;
+            }
+            _ => match star {
+                Some((star_index, covered)) => {
+                    star = Some((star_index, covered + 1));
+                    pattern_index = star_index + 1;
+                    text_index = covered + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[pattern_index..].iter().all(|chr| *chr == '*')
+}
+
+/// the fields of a layer that are known and hold a valid value
+fn valid_fields(base: &Map<String, Value>, fields: &Map<String, Value>) -> Vec<(String, Value)> {
+    fields
+        .iter()
+        .filter(|(key, value)| {
+            let single = Value::Object(Map::from_iter([(key.to_string(), (*value).clone())]));
+            base.contains_key(key.as_str()) && serde_json::from_value::<ModelConfig>(single).is_ok()
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// a model's config assembled from the built-in defaults, ./configs/model.conf, the matching rules and its own file
+#[derive(Clone, Debug)]
+pub struct ConfigLayers {
+    // every field as set by the layers below the model's own file
+    base: Map<String, Value>,
+    // the layer each field of the base comes from
+    sources: HashMap<String, Layer>,
+    // the fields set by the model's own file
+    overrides: Map<String, Value>,
+}
+
+impl ConfigLayers {
+    /// unknown and invalid fields are left out of every layer
+    pub fn new(
+        file_name: &str,
+        defaults: &Map<String, Value>,
+        rules: &[Rule],
+        model: &Map<String, Value>,
+    ) -> Self {
+        let mut base = match serde_json::to_value(ModelConfig::default()) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Map::new(),
+        };
+        base.remove("version");
+        let mut sources: HashMap<String, Layer> = base
+            .keys()
+            .map(|key| (key.clone(), Layer::Builtin))
+            .collect();
+
+        for (key, value) in valid_fields(&base, defaults) {
+            sources.insert(key.clone(), Layer::Defaults);
+            base.insert(key, value);
+        }
+        for rule in rules.iter().filter(|rule| rule.matches(file_name)) {
+            for (key, value) in valid_fields(&base, &rule.config) {
+                sources.insert(key.clone(), Layer::Rule(rule.pattern.clone()));
+                base.insert(key, value);
+            }
+        }
+        let overrides = valid_fields(&base, model).into_iter().collect();
+        Self {
+            base,
+            sources,
+            overrides,
+        }
+    }
+    pub fn resolve(&self) -> ModelConfig {
+        let mut fields = self.base.clone();
+        fields.extend(self.overrides.clone());
+        serde_json::from_value(Value::Object(fields)).unwrap_or_default()
+    }
+    pub fn source(&self, key: &str) -> Layer {
+        if self.overrides.contains_key(key) {
+            Layer::Model
+        } else {
+            self.sources.get(key).cloned().unwrap_or(Layer::Builtin)
+        }
+    }
+    /// sets the model's own value of a field, none removes it so that the field follows the layers below again
+    pub fn set(&mut self, key: &str, value: Option<Value>) {
+        match value {
+            Some(value) => self.overrides.insert(key.to_string(), value),
+            None => self.overrides.remove(key),
+        };
+    }
+    /// leaves out the fields of the model's own file that only repeat the layers below, so that it follows them
+    pub fn prune(&mut self) {
+        let base = &self.base;
+        self.overrides
+            .retain(|key, value| base.get(key).is_none_or(|base| base != value));
+    }
+    /// writes the model's own file, which only holds the fields it overrides
+    pub fn save<P: AsRef<Path>>(&self, filepath: P) -> io::Result<()> {
+        let mut fields = Map::from_iter([(String::from("version"), ModelConfig::VERSION.into())]);
+        fields.extend(self.overrides.clone());
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(filepath)?;
+        file.write_all(
+            serde_json::to_string_pretty(&Value::Object(fields))
+                .unwrap()
+                .as_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(fields) => fields,
+            _ => Map::new(),
+        }
+    }
+
+    fn rule(pattern: &str, config: Value) -> Rule {
+        Rule {
+            pattern: pattern.to_string(),
+            config: fields(config),
+        }
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*.gguf", "mistral-7b.Q4.gguf"));
+        assert!(glob_match("mistral*", "Mistral-7B.gguf"));
+        assert!(glob_match("*mistral*", "openhermes-MISTRAL-7b.gguf"));
+        assert!(glob_match("**q?_k*", "llama-q4_k_m.gguf"));
+        assert!(glob_match("llama-??b.gguf", "llama-13b.gguf"));
+
+        assert!(!glob_match("llama-??b.gguf", "llama-7b.gguf"));
+        assert!(!glob_match("*.gguf", "model.gguf.part"));
+        assert!(!glob_match("mistral*", "openhermes-mistral"));
+        assert!(!glob_match("?", ""));
+        assert!(!glob_match("", "model"));
+    }
+
+    #[test]
+    fn takes_each_field_from_the_highest_layer() {
+        let defaults = fields(json!({"seed": 1, "history_depth": 1, "system_prompt": "defaults"}));
+        let rules = [
+            rule("*mistral*", json!({"seed": 2, "history_depth": 2})),
+            rule("*7b*", json!({"seed": 3})),
+            rule("*llama*", json!({"system_prompt": "not matched"})),
+        ];
+        let model = fields(json!({"seed": 4}));
+        let layers = ConfigLayers::new("mistral-7b.gguf", &defaults, &rules, &model);
+
+        let config = layers.resolve();
+        assert_eq!(config.seed, 4);
+        assert_eq!(config.history_depth, 2);
+        assert_eq!(config.system_prompt, "defaults");
+        assert!(!config.ps_displayed);
+
+        assert_eq!(layers.source("seed"), Layer::Model);
+        assert_eq!(
+            layers.source("history_depth"),
+            Layer::Rule(String::from("*mistral*"))
+        );
+        assert_eq!(layers.source("system_prompt"), Layer::Defaults);
+        assert_eq!(layers.source("ps_displayed"), Layer::Builtin);
+    }
+
+    #[test]
+    fn leaves_out_unknown_and_invalid_fields() {
+        let defaults = fields(json!({"seed": "random", "colour": "red"}));
+        let model = fields(json!({"history_depth": -1}));
+        let layers = ConfigLayers::new("model.gguf", &defaults, &[], &model);
+
+        let config = layers.resolve();
+        assert_eq!(config.seed, -1);
+        assert_eq!(config.history_depth, 3);
+        assert_eq!(layers.source("seed"), Layer::Builtin);
+        assert_eq!(layers.source("history_depth"), Layer::Builtin);
+    }
+
+    #[test]
+    fn prunes_the_values_repeating_the_layers_below() {
+        let defaults = fields(json!({"seed": 1}));
+        let rules = [rule("*", json!({"history_depth": 2}))];
+        let model = fields(json!({"seed": 1, "history_depth": 5, "ps_displayed": false}));
+        let mut layers = ConfigLayers::new("model.gguf", &defaults, &rules, &model);
+        layers.set("system_prompt", Some(Value::from("be brief")));
+        layers.prune();
+
+        assert_eq!(layers.source("seed"), Layer::Defaults);
+        assert_eq!(layers.source("ps_displayed"), Layer::Builtin);
+        assert_eq!(layers.source("history_depth"), Layer::Model);
+        assert_eq!(layers.source("system_prompt"), Layer::Model);
+
+        let config = layers.resolve();
+        assert_eq!(config.seed, 1);
+        assert_eq!(config.history_depth, 5);
+        assert_eq!(config.system_prompt, "be brief");
+
+        layers.set("history_depth", None);
+        assert_eq!(layers.resolve().history_depth, 2);
+        assert_eq!(
+            layers.source("history_depth"),
+            Layer::Rule(String::from("*"))
+        );
+    }
+}
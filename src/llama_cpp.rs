@@ -0,0 +1,424 @@
+use crate::{
+    backend::{
+        Generation, GenerationEvent, InferenceBackend, Interrupt, Prompt, StopReason, TextStream,
+        Timings,
+    },
+    configs::ModelConfig,
+    paths, process,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{Child, ChildStderr, ChildStdout, Command, ExitStatus, Stdio},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// runs every prompt through a fresh llama.cpp 'main' process
+pub struct LlamaCppBackend {
+    executable: PathBuf,
+}
+
+impl Default for LlamaCppBackend {
+    fn default() -> Self {
+        Self {
+            executable: paths::get().llama_bin.clone(),
+        }
+    }
+}
+
+impl InferenceBackend for LlamaCppBackend {
+    fn generate(
+        &mut self,
+        model: &Path,
+        config: &ModelConfig,
+        prompt: &Prompt,
+        _timeout: Duration,
+    ) -> io::Result<Generation> {
+        let mut args: Vec<String> = config.to_args();
+        args.push("--model".to_string());
+        args.push(model.to_string_lossy().to_string());
+        args.push("--prompt".to_string());
+        args.push(prompt.text.clone());
+        // llama.cpp would otherwise echo the prompt back before the reply
+        args.push("--no-display-prompt".to_string());
+
+        let mut child = process::spawn(
+            Command::new(&self.executable)
+                .args(args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .stdin(Stdio::null()),
+        )?;
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        let child = SharedChild(Arc::new(Mutex::new(Some(child))));
+
+        Ok(Generation::spawn(
+            Box::new(LlamaCppStream {
+                child: child.clone(),
+                stdout,
+                stderr: Some(stderr),
+                stderr_reader: None,
+                buffer: [0; 2048],
+                undecoded: Vec::new(),
+                generated: false,
+                token_limit: config.tokens_to_predict(),
+                pending: VecDeque::new(),
+            }),
+            Some(Box::new(child)),
+        ))
+    }
+}
+
+/// the llama.cpp process, terminated by the interrupt or waited for by the stream once it closes its output
+#[derive(Clone)]
+struct SharedChild(Arc<Mutex<Option<Child>>>);
+
+impl SharedChild {
+    /// how the process exited, none if it was terminated
+    fn wait(&self) -> Option<ExitStatus> {
+        // taken out first, the interface mustn't wait on the lock to stop the generation
+        let child = self.0.lock().unwrap().take()?;
+        process::wait(child).ok()
+    }
+}
+
+impl Interrupt for SharedChild {
+    fn interrupt(self: Box<Self>) {
+        if let Some(child) = self.0.lock().unwrap().take() {
+            process::terminate(child);
+        }
+    }
+}
+
+struct LlamaCppStream {
+    child: SharedChild,
+    stdout: ChildStdout,
+    stderr: Option<ChildStderr>,
+    stderr_reader: Option<JoinHandle<StderrReport>>,
+    buffer: [u8; 2048],
+    // bytes of a multi-byte character split across two reads
+    undecoded: Vec<u8>,
+    // whether any text was generated
+    generated: bool,
+    // the number of tokens to predict, negative when unlimited
+    token_limit: i32,
+    // events waiting to be returned once stdout has been closed
+    pending: VecDeque<GenerationEvent>,
+}
+
+impl LlamaCppStream {
+    /// decodes as much of the pending bytes as possible, keeping an incomplete trailing character for later
+    fn decode(&mut self) -> String {
+        match std::str::from_utf8(&self.undecoded) {
+            Ok(text) => {
+                let text = text.to_string();
+                self.undecoded.clear();
+                text
+            }
+            Err(error) if error.error_len().is_none() => {
+                let valid = error.valid_up_to();
+                let text = String::from_utf8_lossy(&self.undecoded[..valid]).to_string();
+                self.undecoded.drain(..valid);
+                text
+            }
+            Err(_) => String::from_utf8_lossy(&std::mem::take(&mut self.undecoded)).to_string(),
+        }
+    }
+    /// waits for stderr to be closed and the process to exit, then turns what was reported into the final events
+    /// errors on stderr only fail the generation if nothing came of it or llama.cpp exited with a failure,
+    /// llama.cpp also logs errors it recovers from, i.e. when no GPU is found and it falls back to the CPU
+    fn conclude(&mut self) -> io::Result<GenerationEvent> {
+        let report = self
+            .stderr_reader
+            .take()
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default();
+        let failure = self.child.wait().filter(|status| !status.success());
+        let generated = self.generated || report.timings.is_some();
+        if !report.errors.is_empty() && (failure.is_some() || !generated) {
+            return Err(io::Error::other(report.errors.join(" | ")));
+        }
+        if let Some(status) = failure {
+            return Err(io::Error::other(match report.last_line {
+                Some(line) => format!("llama.cpp exited with a failure ({}) => {}", status, line),
+                None => format!("llama.cpp exited with a failure ({})", status),
+            }));
+        }
+        if !generated {
+            return Err(io::Error::other(match report.last_line {
+                Some(line) => format!("llama.cpp exited without generating anything => {}", line),
+                None => "llama.cpp exited without generating anything".to_string(),
+            }));
+        }
+        if !report.errors.is_empty() {
+            self.pending
+                .push_back(GenerationEvent::Warning(report.errors.join(" | ")));
+        }
+        // llama.cpp doesn't say why it stopped, reaching the limit exactly is taken as having been cut off
+        let stop_reason = match report.timings.as_ref() {
+            Some(timings)
+                if self.token_limit > 0 && timings.generated_tokens >= self.token_limit as u32 =>
+            {
+                StopReason::TokenLimit
+            }
+            _ => StopReason::Eos,
+        };
+        if let Some(timings) = report.timings {
+            self.pending.push_back(GenerationEvent::Timings(timings));
+        }
+        self.pending
+            .push_back(GenerationEvent::Finished(stop_reason));
+        Ok(self.pending.pop_front().unwrap())
+    }
+}
+
+impl TextStream for LlamaCppStream {
+    fn attach(&mut self, events: Sender<GenerationEvent>) {
+        if let Some(stderr) = self.stderr.take() {
+            self.stderr_reader = Some(thread::spawn(move || read_stderr(stderr, events)));
+        }
+    }
+    fn next_event(&mut self) -> io::Result<GenerationEvent> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(event);
+        }
+        loop {
+            let n = self.stdout.read(&mut self.buffer)?;
+            if n == 0 {
+                return self.conclude();
+            }
+            self.undecoded.extend_from_slice(&self.buffer[..n]);
+            let text = self.decode();
+            if !text.is_empty() {
+                self.generated = true;
+                return Ok(GenerationEvent::Text(text));
+            }
+        }
+    }
+}
+
+/// what llama.cpp reported on stderr once it has exited
+#[derive(Default)]
+struct StderrReport {
+    errors: Vec<String>,
+    timings: Option<Timings>,
+    last_line: Option<String>,
+}
+
+/// follows llama.cpp's log, sending its loading progress as it goes
+fn read_stderr(stderr: ChildStderr, events: Sender<GenerationEvent>) -> StderrReport {
+    let mut report = StderrReport::default();
+    let mut timings = Timings::default();
+    let mut status = "";
+    for line in BufReader::new(stderr).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let new_status = if line.starts_with("llama_model_load")
+            || line.starts_with("llm_load")
+            || line.starts_with("llama_model_loader")
+        {
+            "loading the model..."
+        } else if line.starts_with("llama_new_context") {
+            "creating the context..."
+        } else if line.starts_with("system_info") || line.starts_with("sampling") {
+            "processing the prompt..."
+        } else {
+            status
+        };
+        if new_status != status {
+            status = new_status;
+            // nobody is listening anymore once the generation has been stopped, which is fine
+            let _ = events.send(GenerationEvent::Status(status.to_string()));
+        }
+
+        if is_error(line) {
+            report.errors.push(line.to_string());
+        }
+
+        // 'llama_print_timings' in older releases, 'llama_perf_context_print' in newer ones
+        if let Some(timing) = line
+            .strip_prefix("llama_print_timings:")
+            .or_else(|| line.strip_prefix("llama_perf_context_print:"))
+        {
+            let timing = timing.trim_start();
+            if timing.starts_with("prompt eval time") {
+                if let Some((count, per_second)) = parse_timing(timing) {
+                    timings.prompt_tokens = count;
+                    timings.prompt_tokens_per_second = per_second;
+                }
+            } else if timing.starts_with("eval time") {
+                if let Some((count, per_second)) = parse_timing(timing) {
+                    timings.generated_tokens = count;
+                    timings.generated_tokens_per_second = per_second;
+                    report.timings = Some(timings.clone());
+                }
+            }
+        }
+        report.last_line = Some(line.to_string());
+    }
+    report
+}
+
+/// what llama.cpp prints when it gives up, i.e. 'error: unknown argument: --foo' or 'main: error: unable to load model'
+/// lines merely saying something failed, i.e. 'ggml_cuda_init: failed to initialize CUDA', are often recovered from
+fn is_error(line: &str) -> bool {
+    let lowercase = line.to_lowercase();
+    if lowercase.starts_with("warning") {
+        return false;
+    }
+    lowercase.starts_with("error:")
+        || lowercase.contains(": error:")
+        || lowercase.contains("failed to load model")
+        || lowercase.contains("unable to load model")
+        || lowercase.contains("unknown argument")
+        || lowercase.contains("invalid argument")
+        || lowercase.contains("out of memory")
+        || lowercase.starts_with("terminate called")
+        || line.contains("GGML_ASSERT")
+}
+
+/// parses 'prompt eval time =  456.78 ms /  20 tokens (  22.84 ms per token,  43.78 tokens per second)'
+fn parse_timing(timing: &str) -> Option<(u32, f64)> {
+    let (_, counts) = timing.split_once('/')?;
+    let count = counts.split_whitespace().next()?.parse().ok()?;
+    let per_second = counts
+        .split(',')
+        .nth(1)?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    Some((count, per_second))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::{Message, Role},
+        mock,
+    };
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+
+    /// a shell script standing in for llama.cpp, which fails unless asked not to echo the prompt and then runs the given commands
+    fn fake_llama(name: &str, commands: &str) -> LlamaCppBackend {
+        let directory = env::temp_dir().join(format!("sulmo-tests-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let executable = directory.join(name);
+        fs::write(
+            &executable,
+            format!(
+                "#!/bin/sh\ncase \"$*\" in *--no-display-prompt*) ;; *) exit 2 ;; esac\n{}\n",
+                commands
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&executable, fs::Permissions::from_mode(0o755)).unwrap();
+        LlamaCppBackend { executable }
+    }
+
+    fn generate(mut backend: LlamaCppBackend) -> Vec<GenerationEvent> {
+        let prompt = Prompt {
+            text: String::from("say hello"),
+            messages: vec![Message {
+                role: Role::User,
+                content: String::from("say hello"),
+            }],
+        };
+        let generation = loop {
+            match backend.generate(
+                Path::new("model.gguf"),
+                &ModelConfig::default(),
+                &prompt,
+                Duration::from_secs(5),
+            ) {
+                // ETXTBSY, another test may have forked while the script was still open for writing
+                Err(error) if error.raw_os_error() == Some(26) => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                result => break result.unwrap(),
+            }
+        };
+        mock::events(&generation)
+    }
+
+    fn text(events: &[GenerationEvent]) -> String {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                GenerationEvent::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tells_errors_from_other_lines() {
+        assert!(is_error("error: unknown argument: --foo"));
+        assert!(is_error("main: error: unable to load model"));
+        assert!(is_error("llama_load_model_from_file: failed to load model"));
+        assert!(is_error("GGML_ASSERT: ggml.c:1234: false"));
+        assert!(!is_error(
+            "ggml_cuda_init: failed to initialize CUDA: no CUDA-capable device is detected"
+        ));
+        assert!(!is_error("warning: not compiled with GPU offload support"));
+        assert!(!is_error(
+            "llama_model_loader: - kv  10: general.name str = error-free"
+        ));
+    }
+
+    #[test]
+    fn warns_about_errors_it_recovered_from() {
+        let events = generate(fake_llama(
+            "recovers",
+            "echo 'ggml_cuda_init: failed to initialize CUDA' >&2\n\
+             echo 'llama_kv_cache_init: error: the cache was shrunk' >&2\n\
+             printf ' Hello'\n\
+             echo 'llama_print_timings:        eval time =  100.00 ms /     2 runs   (   50.00 ms per token,    20.00 tokens per second)' >&2",
+        ));
+        assert_eq!(text(&events), " Hello");
+        assert!(events.iter().any(|event| matches!(
+            event,
+            GenerationEvent::Warning(warning) if warning == "llama_kv_cache_init: error: the cache was shrunk"
+        )));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, GenerationEvent::Timings(timings) if timings.generated_tokens == 2)));
+        assert!(matches!(
+            events.last(),
+            Some(GenerationEvent::Finished(StopReason::Eos))
+        ));
+    }
+
+    #[test]
+    fn fails_on_errors_when_nothing_was_generated() {
+        let events = generate(fake_llama(
+            "gives-up",
+            "echo 'error: unknown argument: --foo' >&2\nexit 1",
+        ));
+        assert!(matches!(
+            events.last(),
+            Some(GenerationEvent::Failed(error)) if error == "error: unknown argument: --foo"
+        ));
+    }
+
+    #[test]
+    fn fails_when_exiting_with_a_failure() {
+        let events = generate(fake_llama("crashes", "printf ' Hel'\nexit 3"));
+        assert_eq!(text(&events), " Hel");
+        assert!(matches!(
+            events.last(),
+            Some(GenerationEvent::Failed(error)) if error.contains("exited with a failure")
+        ));
+    }
+}
@@ -0,0 +1,579 @@
+use crate::{
+    backend::{
+        next_sse_data, to_io_error, CancelFlag, CancellableReader, Generation, GenerationEvent,
+        InferenceBackend, Prompt, StopReason, TextStream, Timings,
+    },
+    configs::ModelConfig,
+    paths, process,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, ChildStderr, Command, Stdio},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// keeps a llama.cpp server running with the model loaded and streams completions from it
+pub struct LlamaServerBackend {
+    agent: ureq::Agent,
+    server: Option<LocalServer>,
+}
+
+/// a llama.cpp server started by Sulmo
+#[derive(Clone)]
+struct LocalServer {
+    // taken out once the server is shut down, replaced when it's started again on another port
+    process: Arc<Mutex<Option<ServerProcess>>>,
+    // the model and arguments the server was started with
+    model: PathBuf,
+    arguments: Vec<String>,
+}
+
+/// a running llama.cpp server and the end of its log
+struct ServerProcess {
+    child: Child,
+    port: u16,
+    // the latest lines written on stderr, shown when the server doesn't get ready
+    log: Arc<Mutex<VecDeque<String>>>,
+    log_reader: JoinHandle<()>,
+}
+
+impl ServerProcess {
+    // how many lines of the log are kept
+    const LOG_LINES: usize = 8;
+
+    /// starts the server on a port that was free a moment ago, another program can still take it before the server binds it
+    fn spawn(model: &Path, arguments: &[String]) -> io::Result<Self> {
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let mut child = process::spawn(
+            Command::new(LlamaServerBackend::executable())
+                .arg("--model")
+                .arg(model)
+                .args(["--host", "127.0.0.1", "--port", &port.to_string()])
+                .args(arguments)
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .stdin(Stdio::null()),
+        )?;
+        let stderr = child.stderr.take().unwrap();
+        let log = Arc::new(Mutex::new(VecDeque::new()));
+        let log_reader = {
+            let log = log.clone();
+            // stderr is read for as long as the server runs, so that it never blocks on a full pipe
+            thread::spawn(move || read_log(stderr, log))
+        };
+        Ok(Self {
+            child,
+            port,
+            log,
+            log_reader,
+        })
+    }
+    fn url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+    /// the end of the log so far
+    fn log(&self) -> Vec<String> {
+        self.log.lock().unwrap().iter().cloned().collect()
+    }
+    /// the end of the log of a server that has exited, once what it wrote last has been read
+    fn final_log(&self) -> Vec<String> {
+        let start = Instant::now();
+        while !self.log_reader.is_finished() && start.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.log()
+    }
+}
+
+fn read_log(stderr: ChildStderr, log: Arc<Mutex<VecDeque<String>>>) {
+    for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut log = log.lock().unwrap();
+        if log.len() == ServerProcess::LOG_LINES {
+            log.pop_front();
+        }
+        log.push_back(line.to_string());
+    }
+}
+
+/// the message followed by the end of the server's log, if it wrote anything
+fn with_log(message: String, log: Vec<String>) -> String {
+    if log.is_empty() {
+        message
+    } else {
+        format!("{}, its log ends with:\n{}", message, log.join("\n"))
+    }
+}
+
+impl Default for LlamaServerBackend {
+    fn default() -> Self {
+        Self {
+            // reads time out regularly so that a stopped generation is noticed while the server is silent,
+            // i.e. processing the prompt, the server answers a completion's headers right away so they're never cut off
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(Duration::from_secs(5))
+                .timeout_read(Self::READ_POLL)
+                .build(),
+            server: None,
+        }
+    }
+}
+
+impl LlamaServerBackend {
+    // looked for next to llama.cpp's 'main' executable
+    const EXECUTABLES: [&'static str; 2] = ["llama-server", "server"];
+    // how often a blocked read checks whether the generation has been stopped
+    const READ_POLL: Duration = Duration::from_millis(200);
+
+    fn executable() -> PathBuf {
+        let directory = paths::get()
+            .llama_bin
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        Self::EXECUTABLES
+            .iter()
+            .map(|executable| directory.join(executable))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| directory.join(Self::EXECUTABLES[0]))
+    }
+    /// returns the local server for this model, (re)starting it if needed
+    fn local_server(&mut self, model: &Path, config: &ModelConfig) -> io::Result<&LocalServer> {
+        let arguments = config.to_server_args();
+        let reusable = self.server.as_ref().is_some_and(|server| {
+            server.model == model
+                && server.arguments == arguments
+                && server
+                    .process
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .is_some_and(|running| matches!(running.child.try_wait(), Ok(None)))
+        });
+        if !reusable {
+            self.shutdown();
+            let running = ServerProcess::spawn(model, &arguments)?;
+            self.server = Some(LocalServer {
+                process: Arc::new(Mutex::new(Some(running))),
+                model: model.to_path_buf(),
+                arguments,
+            });
+        }
+        Ok(self.server.as_ref().unwrap())
+    }
+    fn shutdown(&mut self) {
+        if let Some(server) = self.server.take() {
+            if let Some(running) = server.process.lock().unwrap().take() {
+                process::terminate(running.child);
+            }
+        }
+    }
+}
+
+impl InferenceBackend for LlamaServerBackend {
+    fn generate(
+        &mut self,
+        model: &Path,
+        config: &ModelConfig,
+        prompt: &Prompt,
+        timeout: Duration,
+    ) -> io::Result<Generation> {
+        let (url, server) = if config.server_url.is_empty() {
+            let server = self.local_server(model, config)?.clone();
+            let url = server
+                .process
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(ServerProcess::url);
+            (url.unwrap_or_default(), Some(server))
+        } else {
+            (config.server_url.trim_end_matches('/').to_string(), None)
+        };
+        let cancel = CancelFlag::default();
+        let stream = ServerStream {
+            agent: self.agent.clone(),
+            cancel: cancel.clone(),
+            deadline: Instant::now() + timeout,
+            url,
+            server,
+            bind_attempts: 1,
+            request: Some(config.to_completion_request(&prompt.text)),
+            reader: None,
+            timings: None,
+            stop_reason: StopReason::Eos,
+            events: None,
+        };
+        // the stream gives up as soon as the flag is set and drops the connection, which makes the server abandon the generation
+        Ok(Generation::spawn(Box::new(stream), Some(Box::new(cancel))))
+    }
+}
+
+impl Drop for LlamaServerBackend {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+struct ServerStream {
+    agent: ureq::Agent,
+    cancel: CancelFlag,
+    // when waiting for the server to be ready is given up on
+    deadline: Instant,
+    url: String,
+    // the server when it was started by Sulmo
+    server: Option<LocalServer>,
+    // how many times the server was started to get a port of its own
+    bind_attempts: usize,
+    // the request waiting to be sent once the server is ready
+    request: Option<serde_json::Value>,
+    reader: Option<BufReader<CancellableReader<Box<dyn Read + Send + Sync>>>>,
+    // timings sent along with the last piece of text, reported once the stream has ended
+    timings: Option<Timings>,
+    stop_reason: StopReason,
+    events: Option<Sender<GenerationEvent>>,
+}
+
+impl ServerStream {
+    // how many times the server is started before giving up on finding it a free port
+    const MAX_BIND_ATTEMPTS: usize = 3;
+
+    /// blocks until the server has finished loading the model, the generation being stopped or the deadline
+    fn wait_until_ready(&mut self) -> io::Result<()> {
+        let mut reported = false;
+        loop {
+            self.cancel.check()?;
+            if Instant::now() >= self.deadline {
+                let log = self.server.as_ref().and_then(|server| {
+                    server
+                        .process
+                        .lock()
+                        .unwrap()
+                        .as_ref()
+                        .map(ServerProcess::log)
+                });
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    with_log(
+                        String::from("the llama.cpp server wasn't ready in time"),
+                        log.unwrap_or_default(),
+                    ),
+                ));
+            }
+            if let Some(server) = self.server.as_ref() {
+                let mut process = server.process.lock().unwrap();
+                let Some(running) = process.as_mut() else {
+                    return Err(io::Error::other("the llama.cpp server was shut down"));
+                };
+                if let Some(status) = running.child.try_wait()? {
+                    let log = running.final_log();
+                    // another program took the port between it being picked and the server binding it
+                    if log.iter().any(|line| line.contains("couldn't bind"))
+                        && self.bind_attempts < Self::MAX_BIND_ATTEMPTS
+                    {
+                        let restarted = ServerProcess::spawn(&server.model, &server.arguments)?;
+                        self.url = restarted.url();
+                        *process = Some(restarted);
+                        self.bind_attempts += 1;
+                        continue;
+                    }
+                    return Err(io::Error::other(with_log(
+                        format!("the llama.cpp server exited early ({})", status),
+                        log,
+                    )));
+                }
+            }
+            match self.agent.get(&format!("{}/health", self.url)).call() {
+                Ok(_) => return Ok(()),
+                // still loading the model
+                Err(ureq::Error::Status(503, _)) => {
+                    if let Some(events) = self.events.as_ref().filter(|_| !reported) {
+                        let _ = events
+                            .send(GenerationEvent::Status("loading the model...".to_string()));
+                        reported = true;
+                    }
+                }
+                // servers without a health endpoint answer anything else once they are up
+                Err(ureq::Error::Status(_, _)) => return Ok(()),
+                Err(ureq::Error::Transport(error)) => {
+                    if self.server.is_none() {
+                        return Err(io::Error::other(error.to_string()));
+                    }
+                }
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+impl TextStream for ServerStream {
+    fn attach(&mut self, events: Sender<GenerationEvent>) {
+        self.events = Some(events);
+    }
+    fn next_event(&mut self) -> io::Result<GenerationEvent> {
+        self.cancel.check()?;
+        if let Some(request) = self.request.take() {
+            self.wait_until_ready()?;
+            let response = self
+                .agent
+                .post(&format!("{}/completion", self.url))
+                .set("Content-Type", "application/json")
+                .send_string(&request.to_string())
+                .map_err(to_io_error)?;
+            self.reader = Some(BufReader::new(CancellableReader::new(
+                response.into_reader(),
+                self.cancel.clone(),
+            )));
+        }
+        let Some(reader) = self.reader.as_mut() else {
+            return Ok(match self.timings.take() {
+                Some(timings) => GenerationEvent::Timings(timings),
+                None => GenerationEvent::Finished(self.stop_reason),
+            });
+        };
+        match next_sse_data(reader)? {
+            Some(data) => {
+                let event: serde_json::Value = serde_json::from_str(&data)?;
+                if event["stop"].as_bool() == Some(true) {
+                    self.reader = None;
+                    if event["stopped_limit"].as_bool() == Some(true) {
+                        self.stop_reason = StopReason::TokenLimit;
+                    }
+                    let timings = &event["timings"];
+                    if timings.is_object() {
+                        self.timings = Some(Timings {
+                            prompt_tokens: timings["prompt_n"].as_u64().unwrap_or_default() as u32,
+                            prompt_tokens_per_second: timings["prompt_per_second"]
+                                .as_f64()
+                                .unwrap_or_default(),
+                            generated_tokens: timings["predicted_n"].as_u64().unwrap_or_default()
+                                as u32,
+                            generated_tokens_per_second: timings["predicted_per_second"]
+                                .as_f64()
+                                .unwrap_or_default(),
+                        });
+                    }
+                }
+                Ok(GenerationEvent::Text(
+                    event["content"].as_str().unwrap_or_default().to_string(),
+                ))
+            }
+            None => {
+                self.reader = None;
+                Ok(GenerationEvent::Finished(self.stop_reason))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::{Message, Role},
+        mock,
+    };
+    use std::{
+        io::Write,
+        sync::mpsc::{self, Receiver},
+    };
+
+    /// what the stand-in server answers a completion with
+    enum Completion {
+        // every event followed by the end of the stream
+        Events(Vec<&'static str>),
+        // the event, then nothing until the client hangs up, which is reported on the channel
+        Silent(&'static str, Sender<()>),
+    }
+
+    /// a stand-in for llama.cpp's server, it's loading the model for the given number of health checks
+    fn stand_in(loading: Option<usize>, completion: Completion) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (requests, received) = mpsc::channel();
+        thread::spawn(move || {
+            let mut health_checks = 0;
+            let mut completion = Some(completion);
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let (path, body) = mock::read_request(&mut stream);
+                if path == "/health" {
+                    health_checks += 1;
+                    let ready = loading.is_some_and(|loading| health_checks > loading);
+                    let response = if ready {
+                        "HTTP/1.1 200 OK\r\nContent-Length: 15\r\n\r\n{\"status\":\"ok\"}"
+                    } else {
+                        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 2\r\n\r\n{}"
+                    };
+                    let _ = stream.write_all(response.as_bytes());
+                    continue;
+                }
+                let _ = requests.send(body);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n",
+                );
+                match completion.take() {
+                    Some(Completion::Events(events)) => events.iter().for_each(|event| {
+                        let _ = write!(stream, "data: {}\n\n", event);
+                    }),
+                    Some(Completion::Silent(event, closed)) => {
+                        let _ = write!(stream, "data: {}\n\n", event);
+                        // the client never sends anything more, a read only ends once it has hung up
+                        stream
+                            .set_read_timeout(Some(Duration::from_secs(5)))
+                            .unwrap();
+                        if let Ok(0) = stream.read(&mut [0; 16]) {
+                            let _ = closed.send(());
+                        }
+                    }
+                    None => (),
+                }
+                break;
+            }
+        });
+        (url, received)
+    }
+
+    fn generate(url: String, timeout: Duration) -> Generation {
+        let mut config = ModelConfig::default();
+        config.server_url = url;
+        let prompt = Prompt {
+            text: String::from("say hello"),
+            messages: vec![Message {
+                role: Role::User,
+                content: String::from("say hello"),
+            }],
+        };
+        LlamaServerBackend::default()
+            .generate(Path::new("model.gguf"), &config, &prompt, timeout)
+            .unwrap()
+    }
+
+    #[test]
+    fn streams_a_completion_once_loaded() {
+        let (url, requests) = stand_in(
+            Some(1),
+            Completion::Events(vec![
+                r#"{"content":"Hel","stop":false}"#,
+                r#"{"content":"lo","stop":false}"#,
+                r#"{"content":"","stop":true,"stopped_limit":true,"timings":{"prompt_n":3,"prompt_per_second":30.0,"predicted_n":2,"predicted_per_second":20.0}}"#,
+            ]),
+        );
+        let generation = generate(url, Duration::from_secs(5));
+        let events = mock::events(&generation);
+
+        let request: serde_json::Value = serde_json::from_str(&requests.recv().unwrap()).unwrap();
+        assert_eq!(request["prompt"], "say hello");
+        assert_eq!(request["stream"], true);
+        assert!(
+            matches!(&events[0], GenerationEvent::Status(status) if status == "loading the model...")
+        );
+        let text: String = events
+            .iter()
+            .filter_map(|event| match event {
+                GenerationEvent::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "Hello");
+        assert!(events.iter().any(|event| matches!(
+            event,
+            GenerationEvent::Timings(timings) if timings.prompt_tokens == 3 && timings.generated_tokens == 2
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(GenerationEvent::Finished(StopReason::TokenLimit))
+        ));
+    }
+
+    #[test]
+    fn gives_up_on_a_server_that_never_loads() {
+        let (url, _) = stand_in(None, Completion::Events(Vec::new()));
+        let generation = generate(url, Duration::from_millis(300));
+        assert!(matches!(
+            mock::events(&generation).last(),
+            Some(GenerationEvent::Failed(error)) if error.contains("wasn't ready")
+        ));
+    }
+
+    #[test]
+    fn hangs_up_when_stopped() {
+        let (closed, hung_up) = mpsc::channel();
+        let (url, _) = stand_in(
+            Some(0),
+            Completion::Silent(r#"{"content":"Hel","stop":false}"#, closed),
+        );
+        let mut generation = generate(url, Duration::from_secs(5));
+        assert!(matches!(
+            generation.wait_next(Duration::from_secs(5)),
+            Some(GenerationEvent::Text(text)) if text == "Hel"
+        ));
+        generation.stop();
+        assert!(hung_up.recv_timeout(Duration::from_secs(2)).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn starts_again_when_the_port_was_taken() {
+        use std::os::unix::fs::PermissionsExt;
+
+        mock::use_temporary_paths();
+        let executable = LlamaServerBackend::executable();
+        let directory = executable.parent().unwrap().to_path_buf();
+        std::fs::create_dir_all(&directory).unwrap();
+        let attempts = directory.join("attempts");
+        let _ = std::fs::remove_file(&attempts);
+        // fails to bind its port the first time, then fails to load the model
+        std::fs::write(
+            &executable,
+            format!(
+                "#!/bin/sh\n\
+                 echo \"$6\" >> '{attempts}'\n\
+                 if [ \"$(wc -l < '{attempts}')\" -eq 1 ]; then\n\
+                 echo \"main: couldn't bind HTTP server socket, port: $6\" >&2\n\
+                 else\n\
+                 echo 'llama_model_load: loading model' >&2\n\
+                 echo 'main: error: failed to load model' >&2\n\
+                 fi\n\
+                 exit 1\n",
+                attempts = attempts.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&executable, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let prompt = Prompt {
+            text: String::from("say hello"),
+            messages: Vec::new(),
+        };
+        // the server is shut down along with the backend
+        let mut backend = LlamaServerBackend::default();
+        let generation = backend
+            .generate(
+                Path::new("model.gguf"),
+                &ModelConfig::default(),
+                &prompt,
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        let events = mock::events(&generation);
+        assert_eq!(
+            std::fs::read_to_string(&attempts).unwrap().lines().count(),
+            2
+        );
+        assert!(matches!(
+            events.last(),
+            Some(GenerationEvent::Failed(error))
+                if error.contains("exited early")
+                    && error.ends_with("llama_model_load: loading model\nmain: error: failed to load model")
+        ));
+    }
+}
@@ -1,44 +1,85 @@
 use clipboard::{ClipboardContext, ClipboardProvider};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    event::{
+        self, DisableBracketedPaste, EnableBracketedPaste, Event, KeyCode, KeyEventKind,
+        KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+        PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    style::Stylize,
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
 use ratatui::{
-    prelude::{Alignment, Backend, Constraint, CrosstermBackend, Direction, Layout, Margin},
+    prelude::{Alignment, Backend, Constraint, CrosstermBackend, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{
-        scrollbar, Block, Borders, Padding, Paragraph, Scrollbar, ScrollbarOrientation,
-        ScrollbarState, Tabs, Wrap,
+        block::Title, scrollbar, Block, Borders, Clear, Padding, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Tabs, Wrap,
     },
     Frame, Terminal,
 };
+use serde_json::{Map, Value};
 use std::{
     io::{self, stdout},
     path::PathBuf,
     time::{Duration, Instant},
 };
 
+mod backend;
+mod cli;
+mod headless;
+#[cfg(test)]
+mod mock;
+mod paths;
+use cli::{Args, Command};
+mod llama_cpp;
+mod llama_server;
+mod openai;
+mod process;
 mod setup;
 use setup::{
-    check_llama_cpp, load_app_configuration, load_default_llama_configuration,
-    load_gguf_models_with_config,
+    check_llama_cpp, find_model, load_app_configuration, load_default_llama_configuration,
+    load_gguf_models_with_config, load_personas, load_rules,
 };
 mod configs;
-use configs::{AppConfig, ModelConfig};
+mod gguf;
+mod highlight;
+mod templates;
+use configs::{AppConfig, Persona};
 mod utils;
-use utils::pathbuf_to_string;
+use utils::{pathbuf_to_string, wrap_line};
 mod conversation;
+mod editor;
+mod export;
+mod history;
+mod import;
+mod layers;
+mod markdown;
+mod search;
+mod sessions;
+mod settings;
 use conversation::Conversation;
+use export::ExportFormat;
+use layers::{ConfigLayers, Rule};
+use markdown::{CodeBlock, RenderCache};
+use search::{Hit, SearchIndex};
+use settings::{Field, FieldKind, APP_FIELDS, MODEL_FIELDS};
 
 const JANUARY_BLUE: Color = Color::Rgb(0, 161, 185);
 const VIVID_MALACHITE: Color = Color::Rgb(0, 185, 24);
+const ERROR_RED: Color = Color::Rgb(205, 49, 49);
 
 #[derive(PartialEq)]
 enum Mode {
     Home,
     Chat,
+    Sessions,
+    Search,
+    Info,
     Settings,
     Exit,
 }
@@ -48,8 +89,11 @@ impl Mode {
         match self {
             Self::Home => 0,
             Self::Chat => 1,
-            Self::Settings => 2,
-            Self::Exit => 3,
+            Self::Sessions => 2,
+            Self::Search => 3,
+            Self::Info => 4,
+            Self::Settings => 5,
+            Self::Exit => 6,
         }
     }
 }
@@ -60,39 +104,116 @@ struct Application {
     mode_index: usize,
     conversations: Vec<Conversation>,
     conversation_index: usize,
+    personas: Vec<Persona>,
+    // the session highlighted in the sessions tab
+    session_cursor: usize,
+    // the new name of the highlighted session while it's being typed
+    renaming: Option<String>,
+    // the system prompt being written for the highlighted session
+    writing_system_prompt: Option<String>,
+    // whether the highlighted session gets deleted if 'd' is pressed again
+    confirm_delete: bool,
+    // outcome of the last action that has no visible effect otherwise, cleared by the next key press
+    notice: String,
+    search_query: String,
+    // built when entering the search tab
+    search_index: Option<SearchIndex>,
+    search_hits: Vec<Hit>,
+    // the hit highlighted in the search tab
+    search_cursor: usize,
+    // the exchange of the current session the chat view should scroll to and highlight
+    jump_to_chunk: Option<usize>,
+    highlighted_chunk: Option<usize>,
+    // whether the chat shows how each reply was generated
+    show_details: bool,
+    // the code blocks of the latest reply and the one highlighted while picking one to copy
+    code_blocks: Vec<CodeBlock>,
+    code_cursor: Option<usize>,
+    // the setting highlighted in the settings tab, app settings first then the model's
+    settings_cursor: usize,
+    // the new value of the highlighted setting while it's being typed
+    editing_setting: Option<String>,
+    // the rendered replies of the chat
+    render_cache: RenderCache,
     scroll: u16,
     scroll_state: ScrollbarState,
+    // the furthest the chat can be scrolled, and how many rows it shows, as of the latest draw at its current width
     max_scroll: u16,
+    page_height: u16,
+    // whether the chat keeps to its bottom as replies come in, until it's scrolled up
+    follow: bool,
+    // how far the model tab is scrolled, kept within its text as of the latest draw
+    info_scroll: u16,
 }
 
 impl Application {
     pub fn new(
         app_config: AppConfig,
-        gguf_models_with_config: Vec<(PathBuf, ModelConfig)>,
+        gguf_models_with_config: Vec<(PathBuf, ConfigLayers)>,
+        personas: Vec<Persona>,
+        preselected: Option<usize>,
     ) -> Self {
+        let conversations: Vec<Conversation> = gguf_models_with_config
+            .into_iter()
+            .map(|unit| Conversation::new(unit.0, unit.1))
+            .collect();
+        // a model chosen on the command line opens straight into its chat
+        let mode = match preselected {
+            Some(_) => Mode::Chat,
+            None => Mode::Home,
+        };
+        let conversation_index = preselected.unwrap_or(0);
         Self {
             app_config,
-            mode: Mode::Home,
-            mode_index: 0,
-            conversations: gguf_models_with_config
-                .into_iter()
-                .map(|unit| Conversation::new(unit.0, unit.1))
-                .collect(),
-            conversation_index: 0,
+            mode_index: mode.to_usize(),
+            mode,
+            session_cursor: conversations[conversation_index].get_session_index(),
+            conversations,
+            conversation_index,
+            personas,
+            renaming: None,
+            writing_system_prompt: None,
+            confirm_delete: false,
+            notice: String::new(),
+            search_query: String::new(),
+            search_index: None,
+            search_hits: Vec::new(),
+            search_cursor: 0,
+            jump_to_chunk: None,
+            highlighted_chunk: None,
+            show_details: false,
+            code_blocks: Vec::new(),
+            code_cursor: None,
+            settings_cursor: 0,
+            editing_setting: None,
+            render_cache: RenderCache::default(),
             scroll: 0,
             scroll_state: ScrollbarState::default(),
             max_scroll: 0,
+            page_height: 0,
+            follow: true,
+            info_scroll: 0,
         }
     }
     pub fn run<B: Backend>(mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
         let mut last_tick = Instant::now();
-        let tick_rate: Duration = Duration::from_millis(self.app_config.tick_rate);
         loop {
             terminal.draw(|frame| self.ui(frame))?;
 
             if event::poll(Duration::from_millis(10)).unwrap() {
-                if let Event::Key(key) = event::read()? {
+                let event = event::read()?;
+                // a paste arrives in one piece rather than as key presses, which would send it at its first line break
+                if let Event::Paste(text) = &event {
+                    if self.mode == Mode::Chat && self.editing_setting.is_none() {
+                        self.conversations[self.conversation_index]
+                            .editor
+                            .insert_str(text);
+                    }
+                }
+                if let Event::Key(key) = event {
                     if key.kind == KeyEventKind::Press {
+                        self.notice.clear();
+                        self.highlighted_chunk = None;
                         if key.modifiers == KeyModifiers::CONTROL {
                             match key.code {
                                 KeyCode::Char('c') => {
@@ -110,65 +231,409 @@ impl Application {
                                     if let Ok(mut ctx) = rctx {
                                         if let Ok(string) = ctx.get_contents() {
                                             self.conversations[self.conversation_index]
-                                                .push_string(&string);
+                                                .editor
+                                                .insert_str(&string);
                                         }
                                     }
                                 }
+                                KeyCode::Char('o') if self.mode == Mode::Chat => {
+                                    self.next_persona();
+                                }
+                                KeyCode::Char('t') if self.mode == Mode::Chat => {
+                                    self.show_details = !self.show_details;
+                                }
+                                KeyCode::Char('e') if self.mode == Mode::Chat => {
+                                    let index = self.conversations[self.conversation_index]
+                                        .get_session_index();
+                                    self.export_session(index, ExportFormat::Markdown);
+                                }
+                                KeyCode::PageUp
+                                    if matches!(
+                                        self.mode,
+                                        Mode::Chat | Mode::Sessions | Mode::Info | Mode::Settings
+                                    ) =>
+                                {
+                                    self.next_model();
+                                }
+                                KeyCode::PageDown
+                                    if matches!(
+                                        self.mode,
+                                        Mode::Chat | Mode::Sessions | Mode::Info | Mode::Settings
+                                    ) =>
+                                {
+                                    self.prev_model();
+                                }
+                                KeyCode::Home if self.mode == Mode::Chat => {
+                                    self.scroll = 0;
+                                    self.follow = false;
+                                }
+                                KeyCode::End if self.mode == Mode::Chat => {
+                                    self.follow = true;
+                                }
+                                KeyCode::Char('b') if self.mode == Mode::Chat => {
+                                    self.toggle_code_picker();
+                                }
+                                KeyCode::Char('s') if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index].reset_child();
+                                }
+                                KeyCode::Char('d') if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index].pop_front();
+                                }
+                                KeyCode::Char('p') if self.mode == Mode::Chat => {
+                                    let conversation =
+                                        &mut self.conversations[self.conversation_index];
+                                    conversation.history.previous(&mut conversation.editor);
+                                }
+                                KeyCode::Char('n') if self.mode == Mode::Chat => {
+                                    let conversation =
+                                        &mut self.conversations[self.conversation_index];
+                                    conversation.history.next(&mut conversation.editor);
+                                }
+                                KeyCode::Char('r') if self.mode == Mode::Chat => {
+                                    let conversation =
+                                        &mut self.conversations[self.conversation_index];
+                                    conversation.history.search_older(&mut conversation.editor);
+                                }
+                                KeyCode::Char('w') if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index]
+                                        .editor
+                                        .delete_word_before();
+                                }
+                                KeyCode::Left if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index]
+                                        .editor
+                                        .word_left();
+                                }
+                                KeyCode::Right if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index]
+                                        .editor
+                                        .word_right();
+                                }
+                                _ => (),
+                            }
+                        } else if let Some(input) = self.editing_setting.as_mut() {
+                            match key.code {
+                                KeyCode::Char(chr) => input.push(chr),
+                                KeyCode::Backspace => {
+                                    input.pop();
+                                }
+                                KeyCode::Enter => {
+                                    let input = self.editing_setting.take().unwrap_or_default();
+                                    match self.selected_setting().parse(&input) {
+                                        Ok(value) => self.change_setting(Some(value)),
+                                        Err(error) => self.notice = error,
+                                    }
+                                }
+                                KeyCode::Esc => self.editing_setting = None,
                                 _ => (),
                             }
-                        } else {
+                        } else if let Some(cursor) =
+                            self.code_cursor.filter(|_| self.mode == Mode::Chat)
+                        {
                             match key.code {
+                                KeyCode::Up => self.code_cursor = Some(cursor.saturating_sub(1)),
+                                KeyCode::Down if cursor + 1 < self.code_blocks.len() => {
+                                    self.code_cursor = Some(cursor + 1)
+                                }
+                                KeyCode::Enter => self.copy_code_block(cursor),
                                 KeyCode::Char(chr) => {
-                                    if self.mode == Mode::Chat {
-                                        self.conversations[self.conversation_index].push_char(chr);
+                                    if let Some(index) = chr
+                                        .to_digit(10)
+                                        .and_then(|digit| (digit as usize).checked_sub(1))
+                                        .filter(|index| *index < self.code_blocks.len())
+                                    {
+                                        self.copy_code_block(index)
                                     }
                                 }
+                                KeyCode::Esc => self.code_cursor = None,
+                                _ => (),
+                            }
+                        } else if self.mode == Mode::Chat
+                            && self.conversations[self.conversation_index]
+                                .history
+                                .search()
+                                .is_some()
+                        {
+                            let conversation = &mut self.conversations[self.conversation_index];
+                            match key.code {
+                                KeyCode::Char(chr) => conversation
+                                    .history
+                                    .search_push(chr, &mut conversation.editor),
+                                KeyCode::Backspace => {
+                                    conversation.history.search_pop(&mut conversation.editor)
+                                }
+                                KeyCode::Enter => conversation.history.accept_search(),
+                                KeyCode::Esc => {
+                                    conversation.history.cancel_search(&mut conversation.editor)
+                                }
+                                _ => (),
+                            }
+                        } else if let Some(name) = self.renaming.as_mut() {
+                            match key.code {
+                                KeyCode::Char(chr) => name.push(chr),
                                 KeyCode::Backspace => {
-                                    if self.mode == Mode::Chat {
+                                    name.pop();
+                                }
+                                KeyCode::Enter => {
+                                    let name = self.renaming.take().unwrap_or_default();
+                                    if !name.trim().is_empty() {
                                         self.conversations[self.conversation_index]
-                                            .pop_back_input();
+                                            .rename_session(self.session_cursor, name);
                                     }
                                 }
+                                KeyCode::Esc => self.renaming = None,
+                                _ => (),
+                            }
+                        } else if let Some(system_prompt) = self.writing_system_prompt.as_mut() {
+                            match key.code {
+                                KeyCode::Char(chr) => system_prompt.push(chr),
+                                KeyCode::Backspace => {
+                                    system_prompt.pop();
+                                }
+                                KeyCode::Enter => {
+                                    let system_prompt =
+                                        self.writing_system_prompt.take().unwrap_or_default();
+                                    // an empty one goes back to the persona's or the model's
+                                    self.conversations[self.conversation_index]
+                                        .set_session_system_prompt(
+                                            self.session_cursor,
+                                            Some(system_prompt).filter(|system_prompt| {
+                                                !system_prompt.trim().is_empty()
+                                            }),
+                                        );
+                                }
+                                KeyCode::Esc => self.writing_system_prompt = None,
+                                _ => (),
+                            }
+                        } else {
+                            let confirming = std::mem::take(&mut self.confirm_delete);
+                            match key.code {
+                                KeyCode::Char(chr) if self.mode == Mode::Search => {
+                                    self.search_query.push(chr);
+                                    self.run_search();
+                                }
+                                KeyCode::Backspace if self.mode == Mode::Search => {
+                                    self.search_query.pop();
+                                    self.run_search();
+                                }
+                                // shift + enter needs a terminal that tells it apart, alt + enter works everywhere else
+                                KeyCode::Enter
+                                    if self.mode == Mode::Chat
+                                        && key.modifiers.intersects(
+                                            KeyModifiers::SHIFT | KeyModifiers::ALT,
+                                        ) =>
+                                {
+                                    self.conversations[self.conversation_index]
+                                        .editor
+                                        .insert_char('\n');
+                                }
+                                KeyCode::Backspace
+                                    if self.mode == Mode::Chat
+                                        && key.modifiers == KeyModifiers::ALT =>
+                                {
+                                    self.conversations[self.conversation_index]
+                                        .editor
+                                        .delete_word_before();
+                                }
+                                KeyCode::Char(chr) if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index]
+                                        .editor
+                                        .insert_char(chr);
+                                }
+                                KeyCode::Backspace if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index]
+                                        .editor
+                                        .backspace();
+                                }
+                                KeyCode::Delete if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index].editor.delete();
+                                }
+                                KeyCode::Left if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index].editor.left();
+                                }
+                                KeyCode::Right if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index].editor.right();
+                                }
+                                KeyCode::Home if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index].editor.home();
+                                }
+                                KeyCode::End if self.mode == Mode::Chat => {
+                                    self.conversations[self.conversation_index].editor.end();
+                                }
                                 KeyCode::Esc => {
                                     return Ok(());
                                 }
                                 KeyCode::Tab => {
                                     self.next_mode();
                                 }
-                                KeyCode::PageUp => {
-                                    if self.mode == Mode::Chat {
-                                        self.next_model();
-                                    }
+                                KeyCode::Char('n') if self.mode == Mode::Sessions => {
+                                    self.session_cursor =
+                                        self.conversations[self.conversation_index].new_session();
+                                }
+                                KeyCode::Char('r') if self.mode == Mode::Sessions => {
+                                    self.renaming = self.conversations[self.conversation_index]
+                                        .get_sessions()
+                                        .get(self.session_cursor)
+                                        .map(|session| session.name.clone());
+                                }
+                                KeyCode::Char('s') if self.mode == Mode::Sessions => {
+                                    self.writing_system_prompt = self.conversations
+                                        [self.conversation_index]
+                                        .get_sessions()
+                                        .get(self.session_cursor)
+                                        .map(|session| {
+                                            session.system_prompt.clone().unwrap_or_default()
+                                        });
+                                }
+                                KeyCode::Char('m') if self.mode == Mode::Sessions => {
+                                    self.export_session(
+                                        self.session_cursor,
+                                        ExportFormat::Markdown,
+                                    );
+                                }
+                                KeyCode::Char('h') if self.mode == Mode::Sessions => {
+                                    self.export_session(self.session_cursor, ExportFormat::Html);
+                                }
+                                KeyCode::Char('j') if self.mode == Mode::Sessions => {
+                                    self.export_session(self.session_cursor, ExportFormat::Json);
+                                }
+                                KeyCode::Char('i') if self.mode == Mode::Sessions => {
+                                    self.import_sessions();
                                 }
-                                KeyCode::PageDown => {
-                                    if self.mode == Mode::Chat {
-                                        self.prev_model();
+                                KeyCode::Char('d') if self.mode == Mode::Sessions => {
+                                    if confirming {
+                                        let conversation =
+                                            &mut self.conversations[self.conversation_index];
+                                        conversation.delete_session(self.session_cursor);
+                                        self.session_cursor = self
+                                            .session_cursor
+                                            .min(conversation.get_sessions().len() - 1);
+                                    } else {
+                                        self.confirm_delete = true;
                                     }
                                 }
+                                KeyCode::PageUp if self.mode == Mode::Chat => {
+                                    self.scroll = self
+                                        .scroll
+                                        .saturating_sub(self.page_height.saturating_sub(1).max(1));
+                                    self.follow = false;
+                                }
+                                KeyCode::PageDown if self.mode == Mode::Chat => {
+                                    self.scroll = self
+                                        .scroll
+                                        .saturating_add(self.page_height.saturating_sub(1).max(1));
+                                    self.follow = self.scroll >= self.max_scroll;
+                                }
+                                // ctrl + page up and down change the model in the chat too
+                                KeyCode::PageUp
+                                    if matches!(
+                                        self.mode,
+                                        Mode::Sessions | Mode::Info | Mode::Settings
+                                    ) =>
+                                {
+                                    self.next_model();
+                                }
+                                KeyCode::PageDown
+                                    if matches!(
+                                        self.mode,
+                                        Mode::Sessions | Mode::Info | Mode::Settings
+                                    ) =>
+                                {
+                                    self.prev_model();
+                                }
                                 KeyCode::Enter => {
                                     if self.mode == Mode::Exit {
                                         return Ok(());
                                     } else if self.mode == Mode::Chat {
-                                        self.conversations[self.conversation_index].run();
+                                        self.conversations[self.conversation_index]
+                                            .run(&self.app_config);
+                                        // the new reply is followed even if the chat was scrolled up
+                                        self.follow = true;
+                                    } else if self.mode == Mode::Search {
+                                        self.open_search_hit();
+                                    } else if self.mode == Mode::Sessions {
+                                        self.conversations[self.conversation_index]
+                                            .switch_session(self.session_cursor);
+                                        self.follow = true;
+                                        self.mode = Mode::Chat;
+                                        self.mode_index = self.mode.to_usize();
+                                    } else if self.mode == Mode::Settings {
+                                        let field = self.selected_setting();
+                                        match field.kind {
+                                            FieldKind::Toggle | FieldKind::Choice(_) => {
+                                                self.step_setting(true)
+                                            }
+                                            _ => {
+                                                self.editing_setting =
+                                                    Some(field.input(&self.setting_value()))
+                                            }
+                                        }
                                     }
                                 }
-                                KeyCode::End => {
-                                    if self.mode == Mode::Chat {
-                                        self.conversations[self.conversation_index].reset_child();
-                                    }
+                                KeyCode::Up if self.mode == Mode::Search => {
+                                    self.search_cursor = self.search_cursor.saturating_sub(1);
                                 }
-                                KeyCode::Delete => {
-                                    if self.mode == Mode::Chat {
-                                        self.conversations[self.conversation_index].pop_front();
-                                    }
+                                KeyCode::Down
+                                    if self.mode == Mode::Search
+                                        && self.search_cursor + 1 < self.search_hits.len() =>
+                                {
+                                    self.search_cursor += 1;
+                                }
+                                KeyCode::Up if self.mode == Mode::Settings => {
+                                    self.settings_cursor = self.settings_cursor.saturating_sub(1);
+                                }
+                                KeyCode::Down
+                                    if self.mode == Mode::Settings
+                                        && self.settings_cursor + 1
+                                            < APP_FIELDS.len() + MODEL_FIELDS.len() =>
+                                {
+                                    self.settings_cursor += 1;
+                                }
+                                KeyCode::Left if self.mode == Mode::Settings => {
+                                    self.step_setting(false);
+                                }
+                                KeyCode::Right if self.mode == Mode::Settings => {
+                                    self.step_setting(true);
+                                }
+                                KeyCode::Delete if self.mode == Mode::Settings => {
+                                    self.change_setting(None);
+                                }
+                                KeyCode::Up if self.mode == Mode::Sessions => {
+                                    self.session_cursor = self.session_cursor.saturating_sub(1);
+                                }
+                                KeyCode::Down
+                                    if self.mode == Mode::Sessions
+                                        && self.session_cursor + 1
+                                            < self.conversations[self.conversation_index]
+                                                .get_sessions()
+                                                .len() =>
+                                {
+                                    self.session_cursor += 1;
+                                }
+                                KeyCode::Up if self.mode == Mode::Info => {
+                                    self.info_scroll = self.info_scroll.saturating_sub(1);
+                                }
+                                KeyCode::Down if self.mode == Mode::Info => {
+                                    self.info_scroll = self.info_scroll.saturating_add(1);
                                 }
-                                KeyCode::Up => {
+                                // up and down move between the input's rows, and scroll the chat from its first or last one
+                                KeyCode::Up
+                                    if self.mode != Mode::Chat
+                                        || !self.conversations[self.conversation_index]
+                                            .editor
+                                            .up() =>
+                                {
                                     self.scroll = self.scroll.saturating_sub(1);
-                                    self.scroll_state.prev()
+                                    self.follow = false;
                                 }
-                                KeyCode::Down => {
+                                KeyCode::Down
+                                    if self.mode != Mode::Chat
+                                        || !self.conversations[self.conversation_index]
+                                            .editor
+                                            .down() =>
+                                {
                                     self.scroll = self.scroll.saturating_add(1);
-                                    self.scroll_state.next()
+                                    self.follow = self.scroll >= self.max_scroll;
                                 }
                                 _ => (),
                             }
@@ -176,7 +641,8 @@ impl Application {
                     }
                 }
             }
-            if last_tick.elapsed() >= tick_rate {
+            // read on every iteration, the tick rate can be changed from the settings tab
+            if last_tick.elapsed() >= Duration::from_millis(self.app_config.tick_rate) {
                 self.on_tick();
                 last_tick = Instant::now();
             }
@@ -191,6 +657,9 @@ impl Application {
         let tabs = Tabs::new(vec![
             "Home".to_string(),
             pathbuf_to_string(&self.conversations[self.conversation_index].model, 35, "?"),
+            "Sessions".to_string(),
+            "Search".to_string(),
+            "Model".to_string(),
             "Settings".to_string(),
             "Exit".to_string(),
         ])
@@ -202,7 +671,8 @@ impl Application {
         .select(self.mode_index)
         .block(
             Block::new()
-                .title(" Sulmo 1.1.2 ")
+                .title(Title::from(self.notice.as_str()).alignment(Alignment::Left))
+                .title(concat!(" Sulmo ", env!("CARGO_PKG_VERSION"), " "))
                 .borders(Borders::all())
                 .border_type(ratatui::widgets::BorderType::Rounded)
                 .title_alignment(Alignment::Right)
@@ -226,21 +696,63 @@ impl Application {
                 text.push(blank_line.clone());
                 let pgupdown_line = Line::from(vec![
                     Span::styled("Press '", Style::default()),
-                    Span::styled("PgUp", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled("ctrl + PgUp", Style::default().add_modifier(Modifier::BOLD)),
                     Span::styled("' or '", Style::default()),
-                    Span::styled("PgDown", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        "ctrl + PgDown",
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
                     Span::styled("' to change the model.", Style::default()),
                 ]);
                 text.push(pgupdown_line);
                 text.push(blank_line.clone());
-                let enddel_line = Line::from(vec![
+                let stopdel_line = Line::from(vec![
                     Span::styled("Press '", Style::default()),
-                    Span::styled("End", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled("ctrl + s", Style::default().add_modifier(Modifier::BOLD)),
                     Span::styled("' to stop the text generation and '", Style::default()),
-                    Span::styled("Del", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled("ctrl + d", Style::default().add_modifier(Modifier::BOLD)),
                     Span::styled("' to delete the latest exchange.", Style::default()),
                 ]);
-                text.push(enddel_line);
+                text.push(stopdel_line);
+                text.push(blank_line.clone());
+                let newline_line = Line::from(vec![
+                    Span::styled("Press '", Style::default()),
+                    Span::styled("shift + Enter", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled("' or '", Style::default()),
+                    Span::styled("alt + Enter", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        "' to start a new line, the arrow keys, Home, End and ctrl + arrows move the cursor, ctrl + w deletes a word.",
+                        Style::default(),
+                    ),
+                ]);
+                text.push(newline_line);
+                text.push(blank_line.clone());
+                let history_line = Line::from(vec![
+                    Span::styled("Press '", Style::default()),
+                    Span::styled("ctrl + p", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled("' or '", Style::default()),
+                    Span::styled("ctrl + n", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        "' to go through the inputs sent in the session and '",
+                        Style::default(),
+                    ),
+                    Span::styled("ctrl + r", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        "' to search them, again for an older match, Enter keeps it and Esc gives up.",
+                        Style::default(),
+                    ),
+                ]);
+                text.push(history_line);
+                text.push(blank_line.clone());
+                let code_line = Line::from(vec![
+                    Span::styled("Press '", Style::default()),
+                    Span::styled("ctrl + b", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        "' to list the code blocks of the latest reply and copy one of them to your clipboard.",
+                        Style::default(),
+                    ),
+                ]);
+                text.push(code_line);
                 text.push(blank_line.clone());
                 let copy_line = Line::from(vec![
                     Span::styled("Press '", Style::default()),
@@ -259,8 +771,36 @@ impl Application {
                 ]);
                 text.push(paste_line);
                 text.push(blank_line.clone());
+                let persona_line = Line::from(vec![
+                    Span::styled("Press '", Style::default()),
+                    Span::styled("ctrl + o", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        "' to switch between the personas found in ./configs/personas.conf.",
+                        Style::default(),
+                    ),
+                ]);
+                text.push(persona_line);
+                text.push(blank_line.clone());
+                let details_line = Line::from(vec![
+                    Span::styled("Press '", Style::default()),
+                    Span::styled("ctrl + t", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(
+                        "' to show or hide when, with what and how each reply was generated.",
+                        Style::default(),
+                    ),
+                ]);
+                text.push(details_line);
+                text.push(blank_line.clone());
                 text.push(Line::from(
-                    "Use the up and down arrow keys to scroll up and down in chat mode",
+                    "Each model can have several conversations, they are created, renamed, opened and deleted in the sessions tab",
+                ));
+                text.push(blank_line.clone());
+                text.push(Line::from(
+                    "Settings are changed in the settings tab and apply to the next reply, a model's changes are saved to its own config file",
+                ));
+                text.push(blank_line.clone());
+                text.push(Line::from(
+                    "Use the up and down arrow keys, PgUp and PgDown to scroll in chat mode, ctrl + Home and ctrl + End jump to the top and the bottom, where the chat keeps up with the reply being generated",
                 ));
 
                 let paragraph = Paragraph::new(text)
@@ -276,76 +816,197 @@ impl Application {
                 )
             }
             Mode::Chat => {
+                // the input box grows with what's typed, up to a third of the screen
+                let editor = &mut self.conversations[self.conversation_index].editor;
+                let input_rows = editor
+                    .wrap(frame.size().width.saturating_sub(2) as usize)
+                    .clamp(1, (frame.size().height as usize / 3).max(1));
                 let chunks = Layout::default()
                     .direction(Direction::Vertical)
                     .constraints([
                         Constraint::Length(3),
                         Constraint::Min(0),
-                        Constraint::Length(3),
+                        Constraint::Length(input_rows as u16 + 2),
                     ])
                     .split(frame.size());
 
-                let input_line =
-                    Line::from(self.conversations[self.conversation_index].get_usr_input());
-                let input_paragraph = Paragraph::new(input_line)
-                    .alignment(Alignment::Center)
+                let (rows, (column, row)) = editor.view(input_rows);
+                let input_lines: Vec<Line> = rows.into_iter().map(Line::from).collect();
+                let persona_title = match self.conversations[self.conversation_index].get_persona()
+                {
+                    Some(persona) => format!(" {} ", persona.name),
+                    None => String::new(),
+                };
+                let search_title =
+                    match self.conversations[self.conversation_index].history.search() {
+                        Some(search) if search.found.is_none() && !search.query.is_empty() => {
+                            format!(" no input matches '{}' ", search.query)
+                        }
+                        Some(search) => format!(" reverse search : {}_ ", search.query),
+                        None => String::new(),
+                    };
+                let input_paragraph = Paragraph::new(input_lines)
                     .style(Style::default().fg(JANUARY_BLUE))
                     .block(
                         Block::new()
+                            .title(Title::from(search_title).alignment(Alignment::Left))
+                            .title(persona_title)
+                            .title_alignment(Alignment::Right)
                             .borders(Borders::all())
                             .border_type(ratatui::widgets::BorderType::Rounded)
                             .style(Style::default().fg(JANUARY_BLUE)),
                     );
                 frame.render_widget(input_paragraph, chunks[2]);
+                frame.set_cursor(
+                    chunks[2].x + 1 + column as u16,
+                    chunks[2].y + 1 + row as u16,
+                );
 
+                // wrapped here rather than by the paragraph, so that the rows it takes up are known
+                // borders and padding take up 10 columns and 4 rows
+                let width = chunks[1].width.saturating_sub(10) as usize;
+                self.render_cache
+                    .start_frame(width, Style::default().fg(VIVID_MALACHITE));
                 let mut lines = Vec::new();
-                self.conversations[self.conversation_index]
-                    .get_past_conversations_str()
+                // index of the first line of every exchange
+                let mut chunk_starts = Vec::new();
+                let conversation = &self.conversations[self.conversation_index];
+                conversation
+                    .get_past_chunks()
                     .iter()
-                    .for_each(|chunk| {
-                        lines.push(
-                            Line::styled(chunk.0, Style::default().fg(JANUARY_BLUE))
-                                .alignment(Alignment::Right),
-                        );
+                    .enumerate()
+                    .for_each(|(index, chunk)| {
+                        chunk_starts.push(lines.len());
+                        let input_style = if self.highlighted_chunk == Some(index) {
+                            Style::default()
+                                .fg(JANUARY_BLUE)
+                                .add_modifier(Modifier::REVERSED)
+                        } else {
+                            Style::default().fg(JANUARY_BLUE)
+                        };
+                        chunk
+                            .get_input(conversation.config.ps_displayed)
+                            .lines()
+                            .for_each(|line| {
+                                lines.push(
+                                    Line::styled(line, input_style).alignment(Alignment::Right),
+                                )
+                            });
                         lines.push(Line::from(""));
-                        lines.push(
-                            Line::styled(chunk.1, Style::default().fg(VIVID_MALACHITE))
+                        if !chunk.get_output().is_empty() || chunk.get_error().is_empty() {
+                            lines.extend(self.render_cache.rows(chunk.get_output()));
+                        }
+                        if let Some(metadata) = chunk.get_metadata().filter(|_| self.show_details) {
+                            metadata.to_print().into_iter().for_each(|line| {
+                                lines.push(
+                                    Line::styled(line, Style::default().fg(Color::DarkGray))
+                                        .alignment(Alignment::Left),
+                                )
+                            });
+                        }
+                        if !chunk.get_error().is_empty() {
+                            lines.push(
+                                Line::styled(chunk.get_error(), Style::default().fg(ERROR_RED))
+                                    .alignment(Alignment::Left),
+                            );
+                        }
+                        if !chunk.get_warning().is_empty() {
+                            lines.push(
+                                Line::styled(
+                                    chunk.get_warning(),
+                                    Style::default().fg(Color::Yellow),
+                                )
                                 .alignment(Alignment::Left),
-                        );
+                            );
+                        }
+                        if let Some(timings) = chunk.get_timings() {
+                            lines.push(
+                                Line::styled(
+                                    timings.to_string(),
+                                    Style::default().fg(Color::DarkGray),
+                                )
+                                .alignment(Alignment::Left),
+                            );
+                        }
                         lines.push(Line::from(""));
                     });
                 if !self.conversations[self.conversation_index]
                     .get_pro_input()
                     .is_empty()
                 {
-                    lines.push(
-                        Line::styled(
-                            self.conversations[self.conversation_index].get_pro_input(),
-                            Style::default()
-                                .fg(JANUARY_BLUE)
-                                .add_modifier(Modifier::BOLD),
-                        )
-                        .alignment(Alignment::Right),
-                    );
+                    self.conversations[self.conversation_index]
+                        .get_pro_input()
+                        .lines()
+                        .for_each(|line| {
+                            lines.push(
+                                Line::styled(
+                                    line,
+                                    Style::default()
+                                        .fg(JANUARY_BLUE)
+                                        .add_modifier(Modifier::BOLD),
+                                )
+                                .alignment(Alignment::Right),
+                            )
+                        });
                     lines.push(Line::from(""))
                 };
                 if !self.conversations[self.conversation_index]
-                    .get_pro_output()
+                    .get_status()
                     .is_empty()
                 {
                     lines.push(
                         Line::styled(
-                            self.conversations[self.conversation_index].get_pro_output(),
+                            self.conversations[self.conversation_index].get_status(),
                             Style::default()
-                                .fg(VIVID_MALACHITE)
-                                .add_modifier(Modifier::BOLD),
+                                .fg(Color::DarkGray)
+                                .add_modifier(Modifier::ITALIC),
                         )
                         .alignment(Alignment::Left),
                     )
                 };
+                if !self.conversations[self.conversation_index]
+                    .get_pro_output()
+                    .is_empty()
+                {
+                    lines.extend(markdown::render(
+                        self.conversations[self.conversation_index].get_pro_output(),
+                        Style::default()
+                            .fg(VIVID_MALACHITE)
+                            .add_modifier(Modifier::BOLD),
+                    ))
+                };
 
-                self.max_scroll = lines.len() as u16;
-                self.scroll_state = self.scroll_state.content_length(self.max_scroll);
+                // the replies' rows are already wrapped and left as they are
+                let mut rows = Vec::new();
+                // the first row of every line
+                let mut line_rows = Vec::with_capacity(lines.len());
+                lines.into_iter().for_each(|line| {
+                    line_rows.push(rows.len());
+                    rows.extend(wrap_line(line, width));
+                });
+                self.page_height = chunks[1].height.saturating_sub(4);
+                self.max_scroll = rows.len().saturating_sub(self.page_height as usize) as u16;
+                if let Some(start) = self
+                    .jump_to_chunk
+                    .take()
+                    .and_then(|chunk| chunk_starts.get(chunk))
+                {
+                    self.scroll = line_rows[*start] as u16;
+                    self.follow = false;
+                }
+                if self.follow {
+                    self.scroll = self.max_scroll;
+                }
+                self.scroll = self.scroll.min(self.max_scroll);
+                // the thumb is as tall, relative to the track, as the chat's view is relative to the whole chat
+                self.scroll_state = self
+                    .scroll_state
+                    .content_length(self.max_scroll)
+                    .viewport_content_length(
+                        (self.page_height as usize * self.max_scroll as usize / rows.len().max(1))
+                            .max(1) as u16,
+                    )
+                    .position(self.scroll);
 
                 let scrollbar = Scrollbar::default()
                     .orientation(ScrollbarOrientation::VerticalRight)
@@ -353,56 +1014,386 @@ impl Application {
                     .begin_symbol(Some("﹅"))
                     .end_symbol(Some("﹅"));
 
-                let output_paragraph = Paragraph::new(lines)
-                    .scroll((self.scroll, 0))
-                    .block(
+                let output_paragraph = Paragraph::new(rows).scroll((self.scroll, 0)).block(
+                    Block::new()
+                        .padding(Padding::new(4, 4, 1, 1))
+                        .borders(Borders::all())
+                        .border_type(ratatui::widgets::BorderType::Rounded)
+                        .style(Style::default().fg(JANUARY_BLUE)),
+                );
+
+                frame.render_widget(output_paragraph, chunks[1]);
+                frame.render_stateful_widget(scrollbar, chunks[1], &mut self.scroll_state);
+
+                if let Some(cursor) = self.code_cursor {
+                    let lines: Vec<Line> = self
+                        .code_blocks
+                        .iter()
+                        .enumerate()
+                        .map(|(index, block)| {
+                            let style = if index == cursor {
+                                Style::default()
+                                    .fg(VIVID_MALACHITE)
+                                    .add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default()
+                            };
+                            let language = match block.language.as_str() {
+                                "" => "code",
+                                language => language,
+                            };
+                            Line::from(vec![
+                                Span::styled(format!("{}. {}", index + 1, language), style),
+                                Span::styled(
+                                    match block.code.lines().count() {
+                                        1 => String::from("  1 line  "),
+                                        count => format!("  {} lines  ", count),
+                                    },
+                                    Style::default().fg(Color::DarkGray),
+                                ),
+                                Span::styled(
+                                    block
+                                        .code
+                                        .lines()
+                                        .find(|line| !line.trim().is_empty())
+                                        .unwrap_or("")
+                                        .trim()
+                                        .to_string(),
+                                    Style::default().fg(Color::Gray),
+                                ),
+                            ])
+                        })
+                        .collect();
+                    // centered over the chat, as tall as the list allows
+                    let width = (chunks[1].width * 3 / 4).max(20).min(chunks[1].width);
+                    let height = (lines.len() as u16 + 2).min(chunks[1].height);
+                    let area = Rect::new(
+                        chunks[1].x + (chunks[1].width - width) / 2,
+                        chunks[1].y + (chunks[1].height - height) / 2,
+                        width,
+                        height,
+                    );
+                    let scroll = (cursor + 3).saturating_sub(height as usize) as u16;
+                    let picker = Paragraph::new(lines).scroll((scroll, 0)).block(
                         Block::new()
-                            .padding(Padding::new(4, 4, 1, 1))
+                            .title(" copy a code block ")
+                            .padding(Padding::horizontal(1))
+                            .title(
+                                Title::from(" 'Enter' or its number copies it    'Esc' closes ")
+                                    .position(ratatui::widgets::block::Position::Bottom),
+                            )
                             .borders(Borders::all())
                             .border_type(ratatui::widgets::BorderType::Rounded)
                             .style(Style::default().fg(JANUARY_BLUE)),
-                    )
-                    .wrap(Wrap { trim: true });
-
-                frame.render_widget(output_paragraph, chunks[1]);
-                frame.render_stateful_widget(scrollbar, chunks[1], &mut self.scroll_state)
+                    );
+                    frame.render_widget(Clear, area);
+                    frame.render_widget(picker, area);
+                }
             }
-            Mode::Settings => {
+            Mode::Sessions => {
                 let mut text = Vec::new();
-                let blank_line = Line::from("");
+                let conversation = &self.conversations[self.conversation_index];
 
                 text.push(Line::from(Span::styled(
-                    "    App configuration",
+                    "Sessions",
                     Style::default()
                         .fg(VIVID_MALACHITE)
                         .add_modifier(Modifier::BOLD),
                 )));
-                self.app_config
-                    .to_print()
-                    .into_iter()
-                    .for_each(|string| text.push(Line::from(string).alignment(Alignment::Left)));
-                text.push(blank_line);
+                text.push(Line::from(""));
+                conversation
+                    .get_sessions()
+                    .iter()
+                    .enumerate()
+                    .for_each(|(index, session)| {
+                        let marker = if index == conversation.get_session_index() {
+                            "● "
+                        } else {
+                            "  "
+                        };
+                        let style = if index == self.session_cursor {
+                            Style::default()
+                                .fg(VIVID_MALACHITE)
+                                .add_modifier(Modifier::BOLD)
+                        } else {
+                            Style::default()
+                        };
+                        text.push(Line::from(vec![
+                            Span::styled(format!("{}{}", marker, session.name), style),
+                            Span::styled(
+                                match session.chunks.len() {
+                                    1 => String::from("    1 exchange"),
+                                    count => format!("    {} exchanges", count),
+                                },
+                                Style::default().fg(Color::DarkGray),
+                            ),
+                            Span::styled(
+                                if session.system_prompt.is_some() {
+                                    "    own system prompt"
+                                } else {
+                                    ""
+                                },
+                                Style::default().fg(Color::DarkGray),
+                            ),
+                        ]));
+                    });
+                text.push(Line::from(""));
+                if let Some(name) = self.renaming.as_ref() {
+                    text.push(Line::from(format!("new name : {}_", name)));
+                } else if let Some(system_prompt) = self.writing_system_prompt.as_ref() {
+                    text.push(Line::from(format!(
+                        "system prompt, empty to use the persona's or the model's : {}_",
+                        system_prompt
+                    )));
+                } else if self.confirm_delete {
+                    text.push(Line::styled(
+                        "press 'd' again to delete the highlighted session",
+                        Style::default().fg(ERROR_RED),
+                    ));
+                } else {
+                    text.push(Line::styled(
+                        "'Enter' open    'n' new    'r' rename    's' system prompt    'd' delete    'm' 'h' 'j' export to markdown, html or json    'i' import from ./imports",
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+
+                // keeps the highlighted session in view
+                let visible = chunks[1].height.saturating_sub(4) as usize;
+                let scroll = (self.session_cursor + 4).saturating_sub(visible) as u16;
+                let paragraph = Paragraph::new(text).scroll((scroll, 0)).block(
+                    Block::new()
+                        .padding(Padding::new(4, 4, 1, 1))
+                        .borders(Borders::all())
+                        .border_type(ratatui::widgets::BorderType::Rounded)
+                        .style(Style::default().fg(JANUARY_BLUE)),
+                );
+
+                frame.render_widget(paragraph, chunks[1])
+            }
+            Mode::Search => {
+                let mut text = Vec::new();
+
+                text.push(Line::from(vec![
+                    Span::styled(
+                        "Search : ",
+                        Style::default()
+                            .fg(VIVID_MALACHITE)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    Span::from(format!("{}_", self.search_query)),
+                ]));
+                text.push(Line::styled(
+                    match (self.search_index.as_ref(), self.search_query.is_empty()) {
+                        (Some(index), true) => format!(
+                            "type to search the {} exchanges stored across every model and session",
+                            index.len()
+                        ),
+                        (_, false) => format!(
+                            "{} {}, 'Up' 'Down' to select, 'Enter' to open",
+                            self.search_hits.len(),
+                            if self.search_hits.len() == 1 {
+                                "result"
+                            } else {
+                                "results"
+                            }
+                        ),
+                        (None, true) => String::new(),
+                    },
+                    Style::default().fg(Color::DarkGray),
+                ));
+                text.push(Line::from(""));
+
+                // each hit takes up three rows, the highlighted one is kept in view
+                let visible = (chunks[1].height.saturating_sub(7) / 3).max(1) as usize;
+                let first = (self.search_cursor + 1).saturating_sub(visible);
+                self.search_hits
+                    .iter()
+                    .enumerate()
+                    .skip(first)
+                    .take(visible)
+                    .for_each(|(index, hit)| {
+                        let title_style = if index == self.search_cursor {
+                            Style::default()
+                                .fg(VIVID_MALACHITE)
+                                .add_modifier(Modifier::BOLD)
+                        } else {
+                            Style::default()
+                        };
+                        text.push(Line::from(vec![
+                            Span::styled(format!("{} › {}", hit.model, hit.session), title_style),
+                            Span::styled(
+                                format!("    {:.2}", hit.score),
+                                Style::default().fg(Color::DarkGray),
+                            ),
+                        ]));
+                        let mut spans = Vec::new();
+                        let mut position = 0;
+                        hit.matches.iter().for_each(|(start, end)| {
+                            spans.push(Span::from(&hit.snippet[position..*start]));
+                            spans.push(Span::styled(
+                                &hit.snippet[*start..*end],
+                                Style::default()
+                                    .fg(VIVID_MALACHITE)
+                                    .add_modifier(Modifier::BOLD),
+                            ));
+                            position = *end;
+                        });
+                        spans.push(Span::from(&hit.snippet[position..]));
+                        text.push(Line::from(spans));
+                        text.push(Line::from(""));
+                    });
+
+                let paragraph = Paragraph::new(text).block(
+                    Block::new()
+                        .padding(Padding::new(4, 4, 1, 1))
+                        .borders(Borders::all())
+                        .border_type(ratatui::widgets::BorderType::Rounded)
+                        .style(Style::default().fg(JANUARY_BLUE)),
+                );
+
+                frame.render_widget(paragraph, chunks[1])
+            }
+            Mode::Info => {
+                let mut text = Vec::new();
+                let conversation = &self.conversations[self.conversation_index];
+
                 text.push(Line::from(Span::styled(
-                    "    Llama configuration",
+                    "Model information",
                     Style::default()
                         .fg(VIVID_MALACHITE)
                         .add_modifier(Modifier::BOLD),
                 )));
-                self.conversations[self.conversation_index]
-                    .config
-                    .to_print()
+                text.push(Line::from(format!(
+                    "file                         :    '{}'",
+                    conversation.model.display()
+                )));
+                text.push(Line::from(format!(
+                    "chat template in use         :    '{}'",
+                    conversation.get_template_name()
+                )));
+                match conversation.info.as_ref() {
+                    Ok(info) => {
+                        info.to_print_detailed()
+                            .into_iter()
+                            .for_each(|string| text.push(Line::from(string)));
+                        if let Some(template) = info.chat_template.as_ref() {
+                            text.push(Line::from(""));
+                            text.push(Line::from(Span::styled(
+                                "Chat template",
+                                Style::default()
+                                    .fg(VIVID_MALACHITE)
+                                    .add_modifier(Modifier::BOLD),
+                            )));
+                            template
+                                .lines()
+                                .for_each(|line| text.push(Line::from(line.to_string())));
+                        }
+                    }
+                    Err(error) => text.push(Line::styled(
+                        format!("no information available, {}", error),
+                        Style::default().fg(ERROR_RED),
+                    )),
+                }
+
+                // wrapped here so that it can't be scrolled past its end, borders and padding take up 10 columns and 4 rows
+                let width = chunks[1].width.saturating_sub(10) as usize;
+                let rows: Vec<Line> = text
                     .into_iter()
-                    .for_each(|string| text.push(Line::from(string).alignment(Alignment::Left)));
+                    .flat_map(|line| wrap_line(line, width))
+                    .collect();
+                let max_scroll = rows
+                    .len()
+                    .saturating_sub(chunks[1].height.saturating_sub(4) as usize);
+                self.info_scroll = self.info_scroll.min(max_scroll as u16);
+                let paragraph = Paragraph::new(rows).scroll((self.info_scroll, 0)).block(
+                    Block::new()
+                        .padding(Padding::new(4, 4, 1, 1))
+                        .borders(Borders::all())
+                        .border_type(ratatui::widgets::BorderType::Rounded)
+                        .style(Style::default().fg(JANUARY_BLUE)),
+                );
 
-                let paragraph = Paragraph::new(text)
-                    .block(
-                        Block::new()
-                            .padding(Padding::new(4, 4, 1, 1))
-                            .borders(Borders::all())
-                            .border_type(ratatui::widgets::BorderType::Rounded)
-                            .style(Style::default().fg(JANUARY_BLUE)),
-                    )
-                    .wrap(Wrap { trim: true });
+                frame.render_widget(paragraph, chunks[1])
+            }
+            Mode::Settings => {
+                let mut text = Vec::new();
+                let heading = |title: &str| {
+                    Line::from(Span::styled(
+                        title.to_string(),
+                        Style::default()
+                            .fg(VIVID_MALACHITE)
+                            .add_modifier(Modifier::BOLD),
+                    ))
+                };
+                text.push(Line::styled(
+                    "up/down select · left/right change · enter edit · delete reset · page up/down switch model",
+                    Style::default().fg(Color::DarkGray),
+                ));
+                text.push(Line::from(""));
+
+                let conversation = &self.conversations[self.conversation_index];
+                let app_values = serde_json::to_value(self.app_config).unwrap_or_default();
+                let model_values = serde_json::to_value(&conversation.config).unwrap_or_default();
+                let mut cursor_line = 0;
+                for (index, field) in APP_FIELDS.iter().chain(MODEL_FIELDS.iter()).enumerate() {
+                    let app = index < APP_FIELDS.len();
+                    if index == 0 {
+                        text.push(heading("App configuration"));
+                    } else if index == APP_FIELDS.len() {
+                        text.push(Line::from(""));
+                        text.push(heading(&format!(
+                            "Llama configuration of {}",
+                            pathbuf_to_string(&conversation.model, 32, "?")
+                        )));
+                    }
+                    let value = if app {
+                        &app_values[field.key]
+                    } else {
+                        &model_values[field.key]
+                    };
+                    let selected = index == self.settings_cursor;
+                    let shown = match self.editing_setting.as_ref() {
+                        Some(input) if selected => format!("{}█", input),
+                        _ => field.display(value),
+                    };
+                    let mut spans = vec![Span::styled(
+                        format!("{:<29}:    '{}'", field.label, shown),
+                        if selected {
+                            Style::default().add_modifier(Modifier::REVERSED)
+                        } else {
+                            Style::default()
+                        },
+                    )];
+                    // model values are followed by the layer they come from
+                    if !app {
+                        spans.push(Span::styled(
+                            format!("    [{}]", conversation.layers.source(field.key).describe()),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    if selected {
+                        cursor_line = text.len();
+                    }
+                    text.push(Line::from(spans));
+                }
+                if let Ok(info) = conversation.info.as_ref() {
+                    text.push(Line::from(""));
+                    text.push(heading("Model information"));
+                    info.to_print().into_iter().for_each(|string| {
+                        text.push(Line::from(string).alignment(Alignment::Left))
+                    });
+                }
+
+                // keeps the highlighted setting in view
+                let visible = chunks[1].height.saturating_sub(4) as usize;
+                let scroll = (cursor_line + 1).saturating_sub(visible) as u16;
+                let paragraph = Paragraph::new(text).scroll((scroll, 0)).block(
+                    Block::new()
+                        .padding(Padding::new(4, 4, 1, 1))
+                        .borders(Borders::all())
+                        .border_type(ratatui::widgets::BorderType::Rounded)
+                        .style(Style::default().fg(JANUARY_BLUE)),
+                );
 
                 frame.render_widget(paragraph, chunks[1])
             }
@@ -446,41 +1437,175 @@ impl Application {
 }
 
 fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    match args.command {
+        Command::Version => {
+            println!("Sulmo {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Command::Run | Command::Ask => (),
+    }
+    paths::set(args.paths());
+    if args.command == Command::Ask {
+        let code = headless::ask(args.model.as_deref(), &args.prompt);
+        // a stopped generation's process is still being given its grace period
+        process::terminate_all();
+        std::process::exit(code);
+    }
+
     // setup
-    println!("\n         Checking llama-cpp installation...");
-    check_llama_cpp();
-    println!("         Loading default configurations...");
+    println!("\n         Loading default configurations...");
     let app_config: AppConfig = load_app_configuration();
-    let default_llama_config: ModelConfig = load_default_llama_configuration();
+    let default_llama_config: Map<String, Value> = load_default_llama_configuration();
+    let rules: Vec<Rule> = load_rules();
     println!("         Loading gguf models and their configurations...");
-    let gguf_models_config: Vec<(PathBuf, ModelConfig)> =
-        load_gguf_models_with_config(&default_llama_config);
+    let gguf_models_config: Vec<(PathBuf, ConfigLayers)> =
+        load_gguf_models_with_config(&default_llama_config, &rules)
+            .unwrap_or_else(|error| panic!("[{}] {}", "FAILED".red(), error));
+    println!("         Checking llama-cpp installation...");
+    check_llama_cpp(&gguf_models_config);
+    let preselected = args.model.as_deref().and_then(|name| {
+        let found = find_model(&gguf_models_config, name);
+        if found.is_none() {
+            println!(
+                "[ {} ] No model's file name contains \"{}\", starting on the home tab.",
+                "!!!!".yellow(),
+                name
+            );
+        }
+        found
+    });
+    println!("         Loading personas...");
+    let personas: Vec<Persona> = load_personas();
     println!("         Setup complete, entering terminal user interface...\n\n\n");
     std::thread::sleep(std::time::Duration::from_millis(app_config.startup_freeze));
 
+    // makes sure no llama process outlives the application and that the terminal is usable again after a panic
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if std::thread::current().name() == Some("main") {
+            process::terminate_all();
+            let _ = execute!(
+                io::stdout(),
+                PopKeyboardEnhancementFlags,
+                DisableBracketedPaste,
+                LeaveAlternateScreen
+            );
+            let _ = disable_raw_mode();
+        }
+        default_hook(info);
+    }));
+
     // text-user-interface
     let mut stdout = stdout();
     enable_raw_mode().unwrap();
-    let _ = execute!(stdout, EnterAlternateScreen);
+    let _ = execute!(stdout, EnterAlternateScreen, EnableBracketedPaste);
+    // lets shift + enter be told apart from enter, on the terminals that support it
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        let _ = execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        );
+    }
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend).unwrap();
 
-    let application: Application = Application::new(app_config, gguf_models_config);
+    let application: Application =
+        Application::new(app_config, gguf_models_config, personas, preselected);
 
+    // the application and the generations it owns are dropped when it returns, stopping them
     let _ = application.run(&mut terminal);
+    process::terminate_all();
 
-    let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen);
+    let _ = execute!(
+        terminal.backend_mut(),
+        PopKeyboardEnhancementFlags,
+        DisableBracketedPaste,
+        LeaveAlternateScreen
+    );
     let _ = disable_raw_mode();
 }
 
 impl Application {
+    fn selected_setting(&self) -> &'static Field {
+        match self.settings_cursor.checked_sub(APP_FIELDS.len()) {
+            None => &APP_FIELDS[self.settings_cursor],
+            Some(index) => &MODEL_FIELDS[index],
+        }
+    }
+    fn setting_value(&self) -> Value {
+        let key = self.selected_setting().key;
+        let values = if self.settings_cursor < APP_FIELDS.len() {
+            serde_json::to_value(self.app_config)
+        } else {
+            serde_json::to_value(&self.conversations[self.conversation_index].config)
+        };
+        values.unwrap_or_default()[key].clone()
+    }
+    fn step_setting(&mut self, forward: bool) {
+        if let Some(value) = self.selected_setting().step(&self.setting_value(), forward) {
+            self.change_setting(Some(value));
+        }
+    }
+    /// saves the highlighted setting, none resets it to the default or to what the model's config inherits
+    fn change_setting(&mut self, value: Option<Value>) {
+        let field = self.selected_setting();
+        let result = if self.settings_cursor < APP_FIELDS.len() {
+            let mut values = serde_json::to_value(self.app_config).unwrap_or_default();
+            values[field.key] = match value {
+                Some(value) => value,
+                None => serde_json::to_value(AppConfig::default()).unwrap_or_default()[field.key]
+                    .clone(),
+            };
+            match serde_json::from_value::<AppConfig>(values) {
+                Ok(app_config) => {
+                    self.app_config = app_config;
+                    self.app_config.save()
+                }
+                Err(error) => Err(error.into()),
+            }
+        } else {
+            self.conversations[self.conversation_index].set_setting(field.key, value)
+        };
+        if let Err(error) = result {
+            self.notice = format!("failed to save the {}, {}", field.label, error);
+        }
+    }
     fn next_mode(&mut self) {
         match self.mode {
             Mode::Home => {
                 self.mode = Mode::Chat;
                 self.mode_index = self.mode.to_usize();
+                self.follow = true;
             }
             Mode::Chat => {
+                self.mode = Mode::Sessions;
+                self.mode_index = self.mode.to_usize();
+            }
+            Mode::Sessions => {
+                self.mode = Mode::Search;
+                self.mode_index = self.mode.to_usize();
+                self.search_index = Some(SearchIndex::build(&self.conversations));
+                self.run_search();
+            }
+            Mode::Search => {
+                self.mode = Mode::Info;
+                self.mode_index = self.mode.to_usize();
+                self.search_index = None;
+                // the model tab is scrolled with the same keys as the chat, from its top
+                self.info_scroll = 0;
+            }
+            Mode::Info => {
                 self.mode = Mode::Settings;
                 self.mode_index = self.mode.to_usize();
             }
@@ -494,12 +1619,116 @@ impl Application {
             }
         }
     }
+    /// selects the persona after the current one, going back to none after the last
+    fn next_persona(&mut self) {
+        let conversation = &mut self.conversations[self.conversation_index];
+        let next = match conversation.get_persona() {
+            Some(current) => self
+                .personas
+                .iter()
+                .position(|persona| persona.name == current.name)
+                .map_or(0, |index| index + 1),
+            None => 0,
+        };
+        conversation.set_persona(self.personas.get(next).cloned());
+    }
+    /// lists the code blocks of the latest reply, or closes the list
+    fn toggle_code_picker(&mut self) {
+        if self.code_cursor.take().is_some() {
+            return;
+        }
+        self.code_blocks =
+            markdown::code_blocks(self.conversations[self.conversation_index].get_latest_output());
+        if self.code_blocks.is_empty() {
+            self.notice = String::from(" the latest reply has no code block ");
+        } else {
+            self.code_cursor = Some(0);
+        }
+    }
+    fn copy_code_block(&mut self, index: usize) {
+        self.code_cursor = None;
+        let code = match self.code_blocks.get(index) {
+            // without the line break closing the block, so that a pasted command isn't run right away
+            Some(block) => block.code.trim_end_matches('\n').to_string(),
+            None => return,
+        };
+        self.notice = match ClipboardContext::new().and_then(|mut ctx| ctx.set_contents(code)) {
+            Ok(()) => format!(" code block {} copied to the clipboard ", index + 1),
+            Err(error) => format!(" failed to copy the code block, {} ", error),
+        };
+    }
+    fn export_session(&mut self, index: usize, format: ExportFormat) {
+        self.notice =
+            match self.conversations[self.conversation_index].export_session(index, format) {
+                Ok(filepath) => format!(" exported to {} ", filepath.display()),
+                Err(error) => format!(" failed to export the session, {} ", error),
+            };
+    }
+    /// imports the chat exports waiting in ./imports into the current model's sessions
+    fn import_sessions(&mut self) {
+        let files = import::pending_files();
+        if files.is_empty() {
+            self.notice = format!(
+                " no JSON file to import in {} ",
+                import::directory().display()
+            );
+            return;
+        }
+        let mut imported = 0;
+        let mut failures = Vec::new();
+        for file in files {
+            match self.conversations[self.conversation_index].import_file(&file) {
+                Ok(count) => {
+                    imported += count;
+                    let _ = import::mark_imported(&file);
+                }
+                Err(error) => failures.push(format!(
+                    "{} ({})",
+                    file.file_name().unwrap_or("?".as_ref()).to_string_lossy(),
+                    error
+                )),
+            }
+        }
+        self.notice = if failures.is_empty() {
+            format!(" imported {} sessions ", imported)
+        } else {
+            format!(
+                " imported {} sessions, failed to import {} ",
+                imported,
+                failures.join(", ")
+            )
+        };
+    }
+    fn run_search(&mut self) {
+        self.search_hits = match self.search_index.as_ref() {
+            Some(index) => index.search(&self.search_query),
+            None => Vec::new(),
+        };
+        self.search_cursor = 0;
+    }
+    /// opens the session holding the highlighted hit in the chat tab, scrolled to the matching exchange
+    fn open_search_hit(&mut self) {
+        let Some(hit) = self.search_hits.get(self.search_cursor) else {
+            return;
+        };
+        let location = hit.location;
+        self.conversation_index = location.conversation;
+        self.conversations[self.conversation_index].switch_session(location.session);
+        self.session_cursor = location.session;
+        self.jump_to_chunk = Some(location.chunk);
+        self.highlighted_chunk = Some(location.chunk);
+        self.mode = Mode::Chat;
+        self.mode_index = self.mode.to_usize();
+        self.search_index = None;
+    }
     fn next_model(&mut self) {
         if self.conversation_index + 1 < self.conversations.len() {
             self.conversation_index += 1;
         } else {
             self.conversation_index = 0;
         }
+        self.follow = true;
+        self.session_cursor = self.conversations[self.conversation_index].get_session_index();
     }
     fn prev_model(&mut self) {
         if self.conversation_index > 0 {
@@ -507,5 +1736,7 @@ impl Application {
         } else {
             self.conversation_index = self.conversations.len() - 1
         }
+        self.follow = true;
+        self.session_cursor = self.conversations[self.conversation_index].get_session_index();
     }
 }
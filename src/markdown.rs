@@ -0,0 +1,525 @@
+use crate::{highlight::Highlighter, utils::wrap_line};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    mem,
+};
+
+const MARKUP: Style = Style::new().fg(Color::DarkGray);
+const INLINE_CODE: Style = Style::new().fg(Color::Yellow);
+
+/// renders a reply as markdown, an unfinished one, i.e. while it's being generated, is rendered as far as it goes
+/// code blocks that aren't closed yet run to the end of the reply, as they will once it's done
+pub fn render(markdown: &str, style: Style) -> Vec<Line<'static>> {
+    let mut renderer = Renderer {
+        base: style,
+        ..Renderer::default()
+    };
+    Parser::new_ext(markdown, options()).for_each(|event| renderer.event(event));
+    renderer.flush();
+    renderer.lines
+}
+
+/// the rows of finished replies, rendered and wrapped, kept from one frame to the next until the width or style changes
+/// only the reply being generated has to be rendered again at every frame
+#[derive(Default)]
+pub struct RenderCache {
+    width: usize,
+    style: Style,
+    // keyed by a hash of the reply, those of the previous frame and those drawn in the current one
+    previous: HashMap<u64, Vec<Line<'static>>>,
+    drawn: HashMap<u64, Vec<Line<'static>>>,
+}
+
+impl RenderCache {
+    /// starts a frame drawn at the given width and style, replies that weren't drawn in the previous one are dropped
+    pub fn start_frame(&mut self, width: usize, style: Style) {
+        self.previous = mem::take(&mut self.drawn);
+        if width != self.width || style != self.style {
+            self.previous.clear();
+            self.width = width;
+            self.style = style;
+        }
+    }
+    /// the rows of a finished reply, only rendered if it wasn't drawn in the previous frame
+    pub fn rows(&mut self, markdown: &str) -> Vec<Line<'static>> {
+        let mut hasher = DefaultHasher::new();
+        markdown.hash(&mut hasher);
+        let key = hasher.finish();
+        let (width, style) = (self.width, self.style);
+        let rows = self.previous.remove(&key).unwrap_or_else(|| {
+            render(markdown, style)
+                .into_iter()
+                .flat_map(|line| wrap_line(line, width))
+                .collect()
+        });
+        self.drawn.entry(key).or_insert(rows).clone()
+    }
+}
+
+/// a code block of a reply, without its fences
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeBlock {
+    // as given after the opening fence, empty if it wasn't
+    pub language: String,
+    pub code: String,
+}
+
+/// every code block of a reply, in order
+pub fn code_blocks(markdown: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut current: Option<CodeBlock> = None;
+    Parser::new_ext(markdown, options()).for_each(|event| match event {
+        Event::Start(Tag::CodeBlock(kind)) => {
+            current = Some(CodeBlock {
+                language: language(&kind),
+                code: String::new(),
+            })
+        }
+        Event::Text(text) => {
+            if let Some(block) = current.as_mut() {
+                block.code.push_str(&text);
+            }
+        }
+        Event::End(Tag::CodeBlock(_)) => blocks.extend(current.take()),
+        _ => (),
+    });
+    blocks
+}
+
+/// the first word after the opening fence
+fn language(kind: &CodeBlockKind) -> String {
+    match kind {
+        CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or("").to_string(),
+        CodeBlockKind::Indented => String::new(),
+    }
+}
+
+fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+/// what a block holding others puts at the start of its lines
+struct Container {
+    // for its first line, i.e. a list item's bullet
+    first: String,
+    // for the following ones, i.e. as many spaces as the bullet is wide
+    rest: String,
+    style: Style,
+    started: bool,
+}
+
+#[derive(Default)]
+struct Renderer {
+    lines: Vec<Line<'static>>,
+    // the line being put together
+    spans: Vec<Span<'static>>,
+    base: Style,
+    // inline styles currently open, i.e. emphasis inside a link
+    styles: Vec<Style>,
+    containers: Vec<Container>,
+    // the next number of each list currently open, none for bullet lists
+    lists: Vec<Option<u64>>,
+    // the language and text of the code block currently open, it's rendered once closed
+    code: Option<(String, String)>,
+    // the url of every link currently open and where its text starts among the spans
+    links: Vec<(String, usize)>,
+    // the cells of every row of the table currently open, it's rendered once closed so that its columns line up
+    table: Option<Vec<Vec<Vec<Span<'static>>>>>,
+    // whether the next line starts a new block, which is set apart by a blank line
+    new_block: bool,
+}
+
+impl Renderer {
+    fn style(&self) -> Style {
+        self.styles
+            .iter()
+            .fold(self.base, |style, inline| style.patch(*inline))
+    }
+    fn push_span(&mut self, text: &str, style: Style) {
+        if !text.is_empty() {
+            self.spans.push(Span::styled(text.to_string(), style));
+        }
+    }
+    /// ends the line being put together, if anything was put in it
+    fn flush(&mut self) {
+        if !self.spans.is_empty() {
+            let spans = std::mem::take(&mut self.spans);
+            self.push_line(spans);
+        }
+    }
+    fn push_line(&mut self, spans: Vec<Span<'static>>) {
+        if self.new_block && !self.lines.is_empty() {
+            self.lines.push(Line::from(""));
+        }
+        self.new_block = false;
+        let mut line: Vec<Span<'static>> = self
+            .containers
+            .iter_mut()
+            .map(|container| {
+                let prefix = if container.started {
+                    container.rest.clone()
+                } else {
+                    container.started = true;
+                    container.first.clone()
+                };
+                Span::styled(prefix, container.style)
+            })
+            .collect();
+        line.extend(spans);
+        self.lines.push(Line::from(line));
+    }
+    fn end_block(&mut self) {
+        self.flush();
+        self.new_block = true;
+    }
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => match self.code.as_mut() {
+                Some((_, code)) => code.push_str(&text),
+                None => {
+                    let style = self.style();
+                    self.push_span(&text, style);
+                }
+            },
+            Event::Code(code) => {
+                let style = self.style().patch(INLINE_CODE);
+                self.push_span(&code, style);
+            }
+            Event::Html(html) => {
+                let style = self.style();
+                let mut lines = html.lines().peekable();
+                while let Some(line) = lines.next() {
+                    self.push_span(line, style);
+                    if lines.peek().is_some() || html.ends_with('\n') {
+                        self.flush();
+                    }
+                }
+            }
+            Event::FootnoteReference(name) => self.push_span(&format!("[^{}]", name), MARKUP),
+            Event::SoftBreak => {
+                let style = self.style();
+                self.push_span(" ", style);
+            }
+            Event::HardBreak => self.flush(),
+            Event::Rule => {
+                self.end_block();
+                self.push_line(vec![Span::styled("─".repeat(24), MARKUP)]);
+                self.new_block = true;
+            }
+            Event::TaskListMarker(checked) => {
+                self.push_span(if checked { "[x] " } else { "[ ] " }, MARKUP)
+            }
+        }
+    }
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => (),
+            Tag::Heading(level, _, _) => {
+                self.end_block();
+                let style = match level {
+                    HeadingLevel::H1 => {
+                        Style::new().add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+                    }
+                    _ => Style::new().add_modifier(Modifier::BOLD),
+                };
+                self.styles.push(style);
+            }
+            Tag::BlockQuote => {
+                self.end_block();
+                self.containers.push(Container {
+                    first: String::from("│ "),
+                    rest: String::from("│ "),
+                    style: MARKUP,
+                    started: false,
+                });
+                self.styles
+                    .push(Style::new().add_modifier(Modifier::ITALIC));
+            }
+            Tag::CodeBlock(kind) => {
+                self.end_block();
+                self.code = Some((language(&kind), String::new()));
+            }
+            Tag::List(first) => {
+                // a list within an item starts on its own line
+                self.flush();
+                if self.lists.is_empty() {
+                    self.end_block();
+                }
+                self.lists.push(first);
+            }
+            Tag::Item => {
+                self.flush();
+                let depth = self.lists.len();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ if depth.is_multiple_of(2) => String::from("◦ "),
+                    _ => String::from("• "),
+                };
+                self.containers.push(Container {
+                    rest: " ".repeat(marker.chars().count()),
+                    first: marker,
+                    style: self.base,
+                    started: false,
+                });
+            }
+            Tag::FootnoteDefinition(name) => {
+                self.end_block();
+                self.push_span(&format!("[^{}] ", name), MARKUP);
+            }
+            Tag::Table(_) => {
+                self.end_block();
+                self.table = Some(Vec::new());
+            }
+            Tag::TableHead => {
+                self.styles.push(Style::new().add_modifier(Modifier::BOLD));
+                self.table.get_or_insert_with(Vec::new).push(Vec::new());
+            }
+            Tag::TableRow => self.table.get_or_insert_with(Vec::new).push(Vec::new()),
+            Tag::TableCell => self.flush(),
+            Tag::Emphasis => self
+                .styles
+                .push(Style::new().add_modifier(Modifier::ITALIC)),
+            Tag::Strong => self.styles.push(Style::new().add_modifier(Modifier::BOLD)),
+            Tag::Strikethrough => self
+                .styles
+                .push(Style::new().add_modifier(Modifier::CROSSED_OUT)),
+            Tag::Link(_, url, _) | Tag::Image(_, url, _) => {
+                self.links.push((url.to_string(), self.spans.len()));
+                self.styles
+                    .push(Style::new().add_modifier(Modifier::UNDERLINED));
+            }
+        }
+    }
+    fn end(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::FootnoteDefinition(_) => self.end_block(),
+            Tag::Table(_) => {
+                if let Some(rows) = self.table.take() {
+                    self.push_table(rows);
+                }
+                self.new_block = true;
+            }
+            Tag::Heading(..) => {
+                self.styles.pop();
+                self.end_block();
+            }
+            Tag::BlockQuote => {
+                self.flush();
+                self.styles.pop();
+                self.containers.pop();
+                self.new_block = true;
+            }
+            Tag::CodeBlock(_) => {
+                if let Some((language, code)) = self.code.take() {
+                    self.push_code_block(&language, &code);
+                }
+                self.new_block = true;
+            }
+            Tag::List(_) => {
+                self.flush();
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.new_block = true;
+                }
+            }
+            Tag::Item => {
+                self.flush();
+                self.containers.pop();
+            }
+            Tag::TableHead => {
+                self.styles.pop();
+            }
+            Tag::TableRow => (),
+            Tag::TableCell => {
+                let cell = std::mem::take(&mut self.spans);
+                if let Some(row) = self.table.as_mut().and_then(|rows| rows.last_mut()) {
+                    row.push(cell);
+                }
+            }
+            Tag::Emphasis | Tag::Strong | Tag::Strikethrough => {
+                self.styles.pop();
+            }
+            Tag::Link(..) | Tag::Image(..) => {
+                self.styles.pop();
+                if let Some((url, start)) = self.links.pop() {
+                    let text: String = self.spans[start.min(self.spans.len())..]
+                        .iter()
+                        .map(|span| span.content.as_ref())
+                        .collect();
+                    // autolinks already show their url
+                    if !url.is_empty() && text != url {
+                        self.push_span(&format!(" ({})", url), MARKUP);
+                    }
+                }
+            }
+        }
+    }
+    /// every cell padded to the widest of its column, the header set apart from the rows
+    fn push_table(&mut self, rows: Vec<Vec<Vec<Span<'static>>>>) {
+        let width = |cell: &[Span]| cell.iter().map(|span| span.width()).sum::<usize>();
+        let mut widths: Vec<usize> = Vec::new();
+        rows.iter().for_each(|row| {
+            row.iter().enumerate().for_each(|(column, cell)| {
+                if column == widths.len() {
+                    widths.push(0);
+                }
+                widths[column] = widths[column].max(width(cell));
+            })
+        });
+        for (index, row) in rows.into_iter().enumerate() {
+            let mut spans = Vec::new();
+            for (column, cell) in row.into_iter().enumerate() {
+                if column > 0 {
+                    spans.push(Span::styled(" │ ", MARKUP));
+                }
+                let padding = widths[column] - width(&cell);
+                spans.extend(cell);
+                spans.push(Span::raw(" ".repeat(padding)));
+            }
+            self.push_line(spans);
+            if index == 0 {
+                let rule: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+                self.push_line(vec![Span::styled(rule.join("─┼─"), MARKUP)]);
+            }
+        }
+    }
+    /// the code between a bar, headed by its language, each line highlighted
+    fn push_code_block(&mut self, language: &str, code: &str) {
+        let header = match language {
+            "" => String::from("╭─"),
+            language => format!("╭─ {}", language),
+        };
+        self.push_line(vec![Span::styled(header, MARKUP)]);
+        let mut highlighter = Highlighter::new(language);
+        code.lines().for_each(|line| {
+            let mut spans = vec![Span::styled("│ ", MARKUP)];
+            spans.extend(highlighter.line(line));
+            self.push_line(spans);
+        });
+        self.push_line(vec![Span::styled("╰─", MARKUP)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the text of every line, without the trailing padding of table cells
+    fn text(lines: &[Line]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| {
+                line.spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    fn render_text(markdown: &str) -> Vec<String> {
+        text(&render(markdown, Style::default()))
+    }
+
+    #[test]
+    fn renders_an_unclosed_code_block_to_the_end() {
+        let markdown = "Here:\n\n```rust\nfn main() {\n    println!(\"hi\");\n";
+        assert_eq!(
+            render_text(markdown),
+            vec![
+                "Here:",
+                "",
+                "╭─ rust",
+                "│ fn main() {",
+                "│     println!(\"hi\");",
+                "╰─",
+            ]
+        );
+        assert_eq!(
+            code_blocks(markdown),
+            vec![CodeBlock {
+                language: String::from("rust"),
+                code: String::from("fn main() {\n    println!(\"hi\");\n"),
+            }]
+        );
+    }
+
+    #[test]
+    fn finds_every_code_block() {
+        let markdown = "```\nls\n```\n\ntext\n\n```python\nprint(1)\n```\n\n    indented\n";
+        assert_eq!(
+            code_blocks(markdown),
+            vec![
+                CodeBlock {
+                    language: String::new(),
+                    code: String::from("ls\n"),
+                },
+                CodeBlock {
+                    language: String::from("python"),
+                    code: String::from("print(1)\n"),
+                },
+                CodeBlock {
+                    language: String::new(),
+                    code: String::from("indented\n"),
+                },
+            ]
+        );
+        assert_eq!(code_blocks("no code here, only `inline` code"), vec![]);
+    }
+
+    #[test]
+    fn renders_nested_and_unfinished_lists() {
+        assert_eq!(
+            render_text("- one\n- two\n  1. nested\n  2. again\n- three"),
+            vec!["• one", "• two", "  1. nested", "  2. again", "• three"]
+        );
+        assert_eq!(render_text("3. third\n4. fou"), vec!["3. third", "4. fou"]);
+        // an item that has no text yet is left out
+        assert_eq!(render_text("- [x] done\n- "), vec!["• [x] done"]);
+    }
+
+    #[test]
+    fn lines_up_table_columns() {
+        assert_eq!(
+            render_text("| a | bb |\n|---|---|\n| ccc | d |"),
+            vec!["a   │ bb", "────┼───", "ccc │ d"]
+        );
+        // until the delimiter row arrives, it's only a paragraph
+        assert_eq!(render_text("| a | bb |\n|--"), vec!["| a | bb | |--"]);
+    }
+
+    #[test]
+    fn renders_replies_again_only_when_needed() {
+        let style = Style::default().fg(Color::Green);
+        let mut cache = RenderCache::default();
+        cache.start_frame(8, style);
+        let rows = cache.rows("a reply long enough to be wrapped");
+        assert!(rows.len() > 1);
+        assert!(rows.iter().all(|row| row.width() <= 8));
+
+        cache.start_frame(8, style);
+        assert_eq!(cache.previous.len(), 1);
+        assert_eq!(cache.rows("a reply long enough to be wrapped"), rows);
+        // a reply that isn't drawn is dropped, one drawn at another width is rendered again
+        cache.start_frame(8, style);
+        cache.start_frame(8, style);
+        assert!(cache.previous.is_empty());
+        cache.rows("a reply long enough to be wrapped");
+        cache.start_frame(40, style);
+        assert!(cache.previous.is_empty());
+        assert_eq!(cache.rows("a reply long enough to be wrapped").len(), 1);
+    }
+}
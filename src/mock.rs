@@ -0,0 +1,131 @@
+use crate::{
+    backend::{
+        CancelFlag, Generation, GenerationEvent, InferenceBackend, Prompt, StopReason, TextStream,
+    },
+    configs::ModelConfig,
+    paths::{self, Paths},
+};
+use std::{
+    collections::VecDeque,
+    env,
+    io::{self, BufRead, BufReader, Read},
+    net::TcpStream,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// streams scripted events in-process instead of running a model, for tests
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    // sent one after the other by every generation
+    pub events: Vec<GenerationEvent>,
+    // whether the stream stays open once its events are sent, until the generation is stopped
+    pub hold: bool,
+    // the text of every prompt received, oldest first
+    pub prompts: Arc<Mutex<Vec<String>>>,
+    // set once a generation has been stopped
+    pub cancel: CancelFlag,
+}
+
+impl MockBackend {
+    pub fn new(events: Vec<GenerationEvent>) -> Self {
+        Self {
+            events,
+            ..Self::default()
+        }
+    }
+}
+
+impl InferenceBackend for MockBackend {
+    fn generate(
+        &mut self,
+        _model: &Path,
+        _config: &ModelConfig,
+        prompt: &Prompt,
+        _timeout: Duration,
+    ) -> io::Result<Generation> {
+        self.prompts.lock().unwrap().push(prompt.text.clone());
+        let stream = MockStream {
+            events: self.events.clone().into(),
+            hold: self.hold,
+            cancel: self.cancel.clone(),
+        };
+        Ok(Generation::spawn(
+            Box::new(stream),
+            Some(Box::new(self.cancel.clone())),
+        ))
+    }
+}
+
+struct MockStream {
+    events: VecDeque<GenerationEvent>,
+    hold: bool,
+    cancel: CancelFlag,
+}
+
+impl TextStream for MockStream {
+    fn next_event(&mut self) -> io::Result<GenerationEvent> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+        while self.hold && !self.cancel.is_set() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        Ok(GenerationEvent::Finished(StopReason::Eos))
+    }
+}
+
+/// points every path at a temporary folder shared by the tests, so that they leave nothing behind in the repository
+pub fn use_temporary_paths() {
+    paths::set(Paths::within(
+        &env::temp_dir().join(format!("sulmo-tests-{}", std::process::id())),
+    ));
+}
+
+/// every event of a generation up to its end
+pub fn events(generation: &Generation) -> Vec<GenerationEvent> {
+    let mut events = Vec::new();
+    loop {
+        let event = generation
+            .wait_next(Duration::from_secs(5))
+            .expect("the generation never ended");
+        let last = matches!(
+            event,
+            GenerationEvent::Finished(_) | GenerationEvent::Failed(_)
+        );
+        events.push(event);
+        if last {
+            return events;
+        }
+    }
+}
+
+/// the path and body of an HTTP request
+pub fn read_request(stream: &mut TcpStream) -> (String, String) {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let path = line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    (path, String::from_utf8(body).unwrap())
+}
@@ -0,0 +1,284 @@
+use crate::{
+    backend::{
+        next_sse_data, to_io_error, CancelFlag, CancellableReader, Generation, GenerationEvent,
+        InferenceBackend, Prompt, StopReason, TextStream, Timings,
+    },
+    configs::ModelConfig,
+};
+use std::{
+    io::{self, BufReader, Read},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// streams chat completions from any OpenAI-compatible API
+pub struct OpenAiBackend {
+    agent: ureq::Agent,
+}
+
+impl Default for OpenAiBackend {
+    fn default() -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(Duration::from_secs(5))
+                .timeout_read(Self::READ_POLL)
+                .build(),
+        }
+    }
+}
+
+impl OpenAiBackend {
+    // reads time out regularly so that a stopped generation is noticed while the server is silent,
+    // the headers are read with the same timeout and some servers only send them once the model is loaded
+    const READ_POLL: Duration = Duration::from_secs(30);
+}
+
+impl InferenceBackend for OpenAiBackend {
+    fn generate(
+        &mut self,
+        _model: &Path,
+        config: &ModelConfig,
+        prompt: &Prompt,
+        _timeout: Duration,
+    ) -> io::Result<Generation> {
+        if config.server_url.is_empty() {
+            return Err(io::Error::other(
+                "the openai backend needs a server url in the model's configuration",
+            ));
+        }
+        let mut request = self.agent.post(&format!(
+            "{}/chat/completions",
+            config.server_url.trim_end_matches('/')
+        ));
+        if let Some(key) = config.api_key() {
+            request = request.set("Authorization", &format!("Bearer {}", key));
+        }
+        let cancel = CancelFlag::default();
+        let stream = ChatStream {
+            cancel: cancel.clone(),
+            request: Some((request, config.to_chat_request(&prompt.messages))),
+            reader: None,
+            stop_reason: StopReason::Eos,
+            sent: Instant::now(),
+            first_text: None,
+            pending: None,
+        };
+        Ok(Generation::spawn(Box::new(stream), Some(Box::new(cancel))))
+    }
+}
+
+/// a stopped generation is noticed at the next piece of text or read timeout, when the connection is dropped
+struct ChatStream {
+    cancel: CancelFlag,
+    // the request waiting to be sent on the first read
+    request: Option<(ureq::Request, serde_json::Value)>,
+    reader: Option<BufReader<CancellableReader<Box<dyn Read + Send + Sync>>>>,
+    stop_reason: StopReason,
+    // when the request was sent and the first piece of text arrived, the API only reports token counts
+    sent: Instant,
+    first_text: Option<Instant>,
+    // the token counts, when they came in the same chunk as a piece of text
+    pending: Option<GenerationEvent>,
+}
+
+impl ChatStream {
+    /// the token counts of the final usage chunk, speeds are estimated from when the text arrived
+    fn timings(&self, usage: &serde_json::Value) -> Timings {
+        let now = Instant::now();
+        let first_text = self.first_text.unwrap_or(now);
+        let rate = |tokens: u32, duration: Duration| {
+            if duration.is_zero() {
+                0.0
+            } else {
+                tokens as f64 / duration.as_secs_f64()
+            }
+        };
+        let prompt_tokens = usage["prompt_tokens"].as_u64().unwrap_or_default() as u32;
+        let generated_tokens = usage["completion_tokens"].as_u64().unwrap_or_default() as u32;
+        Timings {
+            prompt_tokens,
+            prompt_tokens_per_second: rate(prompt_tokens, first_text - self.sent),
+            generated_tokens,
+            generated_tokens_per_second: rate(generated_tokens, now - first_text),
+        }
+    }
+}
+
+impl TextStream for ChatStream {
+    fn next_event(&mut self) -> io::Result<GenerationEvent> {
+        self.cancel.check()?;
+        if let Some(event) = self.pending.take() {
+            return Ok(event);
+        }
+        if let Some((request, body)) = self.request.take() {
+            self.sent = Instant::now();
+            let response = request
+                .set("Content-Type", "application/json")
+                .send_string(&body.to_string())
+                .map_err(to_io_error)?;
+            self.reader = Some(BufReader::new(CancellableReader::new(
+                response.into_reader(),
+                self.cancel.clone(),
+            )));
+        }
+        let Some(reader) = self.reader.as_mut() else {
+            return Ok(GenerationEvent::Finished(self.stop_reason));
+        };
+        match next_sse_data(reader)? {
+            Some(data) if data != "[DONE]" => {
+                let event: serde_json::Value = serde_json::from_str(&data)?;
+                if let Some(error) = event.get("error") {
+                    return Err(io::Error::other(error.to_string()));
+                }
+                let choice = &event["choices"][0];
+                // read on past the finish reason, the usage chunk comes after it
+                if choice["finish_reason"].as_str() == Some("length") {
+                    self.stop_reason = StopReason::TokenLimit;
+                }
+                let text = choice["delta"]["content"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                if !text.is_empty() && self.first_text.is_none() {
+                    self.first_text = Some(Instant::now());
+                }
+                let timings = event
+                    .get("usage")
+                    .filter(|usage| usage.is_object())
+                    .map(|usage| GenerationEvent::Timings(self.timings(usage)));
+                match timings {
+                    Some(timings) if text.is_empty() => Ok(timings),
+                    timings => {
+                        self.pending = timings;
+                        Ok(GenerationEvent::Text(text))
+                    }
+                }
+            }
+            _ => {
+                self.reader = None;
+                Ok(GenerationEvent::Finished(self.stop_reason))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::{Message, Role},
+        mock,
+    };
+    use std::{io::Write, net::TcpListener, sync::mpsc, thread};
+
+    #[test]
+    fn reports_the_token_usage() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let (requests, received) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let _ = requests.send(mock::read_request(&mut stream));
+            let _ = stream.write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n",
+            );
+            [
+                r#"{"choices":[{"delta":{"content":"Hel"},"finish_reason":null}]}"#,
+                r#"{"choices":[{"delta":{"content":"lo"},"finish_reason":"length"}]}"#,
+                r#"{"choices":[],"usage":{"prompt_tokens":3,"completion_tokens":2,"total_tokens":5}}"#,
+                "[DONE]",
+            ]
+            .iter()
+            .for_each(|event| {
+                let _ = write!(stream, "data: {}\n\n", event);
+            });
+        });
+        let mut config = ModelConfig::default();
+        config.server_url = url;
+        let prompt = Prompt {
+            text: String::new(),
+            messages: vec![Message {
+                role: Role::User,
+                content: String::from("say hello"),
+            }],
+        };
+        let generation = OpenAiBackend::default()
+            .generate(
+                Path::new("remote.openai"),
+                &config,
+                &prompt,
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        let events = mock::events(&generation);
+
+        let (path, body) = received.recv().unwrap();
+        let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(path, "/v1/chat/completions");
+        assert_eq!(request["stream_options"]["include_usage"], true);
+        let text: String = events
+            .iter()
+            .filter_map(|event| match event {
+                GenerationEvent::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, "Hello");
+        assert!(events.iter().any(|event| matches!(
+            event,
+            GenerationEvent::Timings(timings) if timings.prompt_tokens == 3 && timings.generated_tokens == 2
+        )));
+        assert!(matches!(
+            events.last(),
+            Some(GenerationEvent::Finished(StopReason::TokenLimit))
+        ));
+    }
+
+    #[test]
+    fn hangs_up_when_stopped() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let (closed, hung_up) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            mock::read_request(&mut stream);
+            let _ = stream.write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n",
+            );
+            let _ = stream.write_all(
+                b"data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n",
+            );
+            // the client never sends anything more, a read only ends once it has hung up
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            if let Ok(0) = stream.read(&mut [0; 16]) {
+                let _ = closed.send(());
+            }
+        });
+        let mut config = ModelConfig::default();
+        config.server_url = url;
+        let mut backend = OpenAiBackend {
+            agent: ureq::AgentBuilder::new()
+                .timeout_read(Duration::from_millis(50))
+                .build(),
+        };
+        let mut generation = backend
+            .generate(
+                Path::new("remote.openai"),
+                &config,
+                &Prompt {
+                    text: String::new(),
+                    messages: Vec::new(),
+                },
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        assert!(matches!(
+            generation.wait_next(Duration::from_secs(5)),
+            Some(GenerationEvent::Text(text)) if text == "Hel"
+        ));
+        generation.stop();
+        assert!(hung_up.recv_timeout(Duration::from_secs(2)).is_ok());
+    }
+}
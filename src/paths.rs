@@ -0,0 +1,71 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// where Sulmo looks for models and llama.cpp and where it keeps its own files, set once on startup
+#[derive(Clone, Debug)]
+pub struct Paths {
+    pub models: PathBuf,
+    pub configs: PathBuf,
+    // sessions, exports and imports are kept in here
+    pub data: PathBuf,
+    // llama.cpp's 'main' executable, its server is looked for next to it
+    pub llama_bin: PathBuf,
+}
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+/// the paths in use, those of a portable folder until set
+pub fn get() -> &'static Paths {
+    PATHS.get_or_init(|| Paths::within(Path::new(".")))
+}
+
+/// can only be done once, before any path is used
+pub fn set(paths: Paths) {
+    let _ = PATHS.set(paths);
+}
+
+impl Paths {
+    /// everything inside a single folder, the way Sulmo's repository is laid out
+    pub fn within(folder: &Path) -> Self {
+        Self {
+            models: folder.join("models"),
+            configs: folder.join("configs"),
+            data: folder.to_path_buf(),
+            llama_bin: folder.join("llama-cpp").join("main"),
+        }
+    }
+    /// the working directory if it looks like a portable folder, i.e. Sulmo's repository, the XDG base directories otherwise
+    pub fn detect() -> Self {
+        let portable = ["configs", "models", "llama-cpp", "llama.cpp"]
+            .iter()
+            .any(|folder| Path::new(folder).is_dir());
+        match (portable, Self::xdg()) {
+            (false, Some(paths)) => paths,
+            _ => Self::within(Path::new(".")),
+        }
+    }
+    /// configs in $XDG_CONFIG_HOME/sulmo, everything else in $XDG_DATA_HOME/sulmo
+    fn xdg() -> Option<Self> {
+        let home = env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(PathBuf::from);
+        let base = |variable: &str, fallback: &[&str]| {
+            env::var_os(variable)
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .or_else(|| {
+                    home.as_ref()
+                        .map(|home| home.join(fallback.iter().collect::<PathBuf>()))
+                })
+        };
+        let data = base("XDG_DATA_HOME", &[".local", "share"])?.join("sulmo");
+        let configs = base("XDG_CONFIG_HOME", &[".config"])?.join("sulmo");
+        Some(Self {
+            configs,
+            ..Self::within(&data)
+        })
+    }
+}
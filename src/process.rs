@@ -0,0 +1,111 @@
+use std::{
+    io,
+    process::{Child, Command, ExitStatus},
+    sync::{Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+// process groups started by Sulmo that haven't been terminated yet
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+// how long a process is given to exit on its own before being killed
+const GRACE_PERIOD: Duration = Duration::from_millis(1500);
+
+/// the running process groups, still given when a thread panicked holding them so that they're terminated anyway
+fn running() -> MutexGuard<'static, Vec<u32>> {
+    RUNNING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// spawns the command in a process group of its own, so that anything it starts can be terminated along with it
+pub fn spawn(command: &mut Command) -> io::Result<Child> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // CREATE_NEW_PROCESS_GROUP
+        command.creation_flags(0x00000200);
+    }
+    let child = command.spawn()?;
+    running().push(child.id());
+    Ok(child)
+}
+
+/// asks the process group to exit and returns right away, a thread of its own kills it if it's still running
+/// after the grace period, then reaps the child, so that the caller, i.e. the interface, never waits on it
+pub fn terminate(mut child: Child) {
+    let id = child.id();
+    match child.try_wait() {
+        Ok(None) => signal_group(id, Signal::Terminate),
+        // already exited and now reaped
+        Ok(Some(_)) => {
+            running().retain(|running| *running != id);
+            return;
+        }
+        Err(_) => (),
+    }
+    thread::spawn(move || {
+        let start = Instant::now();
+        while start.elapsed() < GRACE_PERIOD && matches!(child.try_wait(), Ok(None)) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        if let Ok(None) = child.try_wait() {
+            signal_group(id, Signal::Kill);
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+        running().retain(|running| *running != id);
+    });
+}
+
+/// waits for a child that's exiting on its own, i.e. once it has closed its output
+pub fn wait(mut child: Child) -> io::Result<ExitStatus> {
+    let status = child.wait();
+    running().retain(|running| *running != child.id());
+    status
+}
+
+/// last resort used when the application exits or panics, terminates every process group still running
+/// including those still given their grace period, it's the only one to block as nothing waits on it anymore
+pub fn terminate_all() {
+    let running: Vec<u32> = running().drain(..).collect();
+    if running.is_empty() {
+        return;
+    }
+    running
+        .iter()
+        .for_each(|id| signal_group(*id, Signal::Terminate));
+    thread::sleep(Duration::from_millis(200));
+    running
+        .iter()
+        .for_each(|id| signal_group(*id, Signal::Kill));
+}
+
+enum Signal {
+    Terminate,
+    Kill,
+}
+
+#[cfg(unix)]
+fn signal_group(id: u32, signal: Signal) {
+    let signal = match signal {
+        Signal::Terminate => libc::SIGTERM,
+        Signal::Kill => libc::SIGKILL,
+    };
+    // a negative pid targets the whole process group
+    unsafe {
+        libc::kill(-(id as libc::pid_t), signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn signal_group(id: u32, _signal: Signal) {
+    // no graceful signal is available for console-less processes, the whole tree is killed instead
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &id.to_string()])
+        .output();
+}
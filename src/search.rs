@@ -0,0 +1,356 @@
+use crate::conversation::Conversation;
+use std::collections::HashMap;
+
+/// every exchange of every session of every model, split into words
+pub struct SearchIndex {
+    documents: Vec<Document>,
+}
+
+struct Document {
+    location: Location,
+    model: String,
+    session: String,
+    input: String,
+    output: String,
+    // how many times each word appears in the exchange
+    terms: HashMap<String, u32>,
+}
+
+/// where an exchange is found, as indices into the conversations, their sessions and their chunks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Location {
+    pub conversation: usize,
+    pub session: usize,
+    pub chunk: usize,
+}
+
+pub struct Hit {
+    pub location: Location,
+    pub model: String,
+    pub session: String,
+    pub score: f64,
+    // a piece of the exchange around the first match
+    pub snippet: String,
+    // byte ranges of the snippet matching the query
+    pub matches: Vec<(usize, usize)>,
+}
+
+// how many hits are shown at most
+const MAX_HITS: usize = 100;
+// how many characters of context are kept around a match
+const CONTEXT_BEFORE: usize = 60;
+const CONTEXT_AFTER: usize = 120;
+
+impl SearchIndex {
+    pub fn build(conversations: &[Conversation]) -> Self {
+        let mut documents = Vec::new();
+        conversations
+            .iter()
+            .enumerate()
+            .for_each(|(conversation_index, conversation)| {
+                let model = conversation
+                    .model
+                    .file_name()
+                    .unwrap_or("?".as_ref())
+                    .to_string_lossy()
+                    .to_string();
+                conversation.get_sessions().iter().enumerate().for_each(
+                    |(session_index, session)| {
+                        // the current session's chunks are the most recent ones
+                        let chunks = if session_index == conversation.get_session_index() {
+                            conversation.get_past_chunks()
+                        } else {
+                            session.chunks.as_slice()
+                        };
+                        chunks.iter().enumerate().for_each(|(chunk_index, chunk)| {
+                            let mut terms = HashMap::new();
+                            tokenize(chunk.get_input(false))
+                                .chain(tokenize(chunk.get_output()))
+                                .for_each(|term| *terms.entry(term).or_insert(0) += 1);
+                            documents.push(Document {
+                                location: Location {
+                                    conversation: conversation_index,
+                                    session: session_index,
+                                    chunk: chunk_index,
+                                },
+                                model: model.clone(),
+                                session: session.name.clone(),
+                                input: chunk.get_input(false).to_string(),
+                                output: chunk.get_output().to_string(),
+                                terms,
+                            });
+                        });
+                    },
+                );
+            });
+        Self { documents }
+    }
+    /// exchanges containing every word of the query, best first
+    /// words match their beginning so that results show up while typing, i.e. 'borr' finds 'borrow'
+    pub fn search(&self, query: &str) -> Vec<Hit> {
+        let query_terms: Vec<String> = tokenize(query).collect();
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+        let phrase = query.trim().to_lowercase();
+
+        let frequencies: Vec<Vec<u32>> = self
+            .documents
+            .iter()
+            .map(|document| {
+                query_terms
+                    .iter()
+                    .map(|query_term| {
+                        document
+                            .terms
+                            .iter()
+                            .filter(|(term, _)| term.starts_with(query_term.as_str()))
+                            .map(|(_, count)| count)
+                            .sum()
+                    })
+                    .collect()
+            })
+            .collect();
+        let total = self.documents.len() as f64;
+        let rarities: Vec<f64> = (0..query_terms.len())
+            .map(|index| {
+                let containing = frequencies
+                    .iter()
+                    .filter(|frequency| frequency[index] > 0)
+                    .count() as f64;
+                (1.0 + total / containing.max(1.0)).ln()
+            })
+            .collect();
+
+        let mut hits: Vec<Hit> = self
+            .documents
+            .iter()
+            .zip(frequencies.iter())
+            .filter(|(_, frequency)| frequency.iter().all(|count| *count > 0))
+            .map(|(document, frequency)| {
+                // rare words weigh more, repetitions of a word less and less
+                let mut score: f64 = frequency
+                    .iter()
+                    .zip(rarities.iter())
+                    .map(|(count, rarity)| {
+                        let count = *count as f64;
+                        rarity * count / (count + 1.2)
+                    })
+                    .sum();
+                let input = document.input.to_lowercase();
+                if input.contains(&phrase) || document.output.to_lowercase().contains(&phrase) {
+                    score += 2.0;
+                }
+                if query_terms.iter().any(|term| input.contains(term.as_str())) {
+                    score += 0.5;
+                }
+                let (snippet, matches) = snippet(document, &phrase, &query_terms);
+                Hit {
+                    location: document.location,
+                    model: document.model.clone(),
+                    session: document.session.clone(),
+                    score,
+                    snippet,
+                    matches,
+                }
+            })
+            .collect();
+        // the most recent exchanges come first among equally good ones
+        hits.reverse();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(MAX_HITS);
+        hits
+    }
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|chr: char| !chr.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// the part of the exchange around the phrase, or around the first word of the query if the phrase isn't found
+fn snippet(
+    document: &Document,
+    phrase: &str,
+    query_terms: &[String],
+) -> (String, Vec<(usize, usize)>) {
+    let text = format!("{} → {}", document.input, document.output).replace('\n', " ");
+    let lowercase = text.to_lowercase();
+    // lowercasing can change the length of some characters, positions are only reliable when it doesn't
+    if lowercase.len() != text.len() {
+        return (
+            text.chars().take(CONTEXT_BEFORE + CONTEXT_AFTER).collect(),
+            Vec::new(),
+        );
+    }
+    let position = lowercase
+        .find(phrase)
+        .or_else(|| lowercase.find(query_terms[0].as_str()))
+        .unwrap_or(0);
+
+    let mut start = position.saturating_sub(CONTEXT_BEFORE);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (position + CONTEXT_AFTER).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < text.len() { "…" } else { "" };
+    let snippet = format!("{}{}{}", prefix, &text[start..end], suffix);
+
+    let lowercase = snippet.to_lowercase();
+    let mut matches: Vec<(usize, usize)> = query_terms
+        .iter()
+        .flat_map(|term| {
+            lowercase
+                .match_indices(term.as_str())
+                .map(|(index, term)| (index, index + term.len()))
+                .collect::<Vec<(usize, usize)>>()
+        })
+        .collect();
+    matches.sort();
+    // overlapping matches are merged so that the snippet can be split along them
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in matches {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    (snippet, merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        configs::ModelConfig,
+        conversation::ConversationChunk,
+        layers::ConfigLayers,
+        mock::{self, MockBackend},
+        sessions::Session,
+    };
+    use serde_json::Map;
+
+    /// a conversation whose sessions hold the given exchanges
+    fn conversation(name: &str, sessions: &[(&str, &[(&str, &str)])]) -> Conversation {
+        mock::use_temporary_paths();
+        let model = crate::paths::get().models.join(format!("{}.gguf", name));
+        let _ = std::fs::remove_dir_all(Session::directory(&model));
+        for (session_name, exchanges) in sessions {
+            let mut session = Session::new(&model, session_name.to_string());
+            session.chunks = exchanges
+                .iter()
+                .map(|(input, output)| {
+                    ConversationChunk::from_exchange(
+                        input.to_string(),
+                        output.to_string(),
+                        String::new(),
+                        None,
+                    )
+                })
+                .collect();
+            session.save(&model).unwrap();
+        }
+        let layers = ConfigLayers::new(name, &Map::new(), &[], &Map::new());
+        Conversation::with_backend(
+            model,
+            ModelConfig::default(),
+            layers,
+            Box::new(MockBackend::new(Vec::new())),
+        )
+    }
+
+    fn matched(hit: &Hit) -> Vec<&str> {
+        hit.matches
+            .iter()
+            .map(|(start, end)| &hit.snippet[*start..*end])
+            .collect()
+    }
+
+    #[test]
+    fn finds_exchanges_across_sessions_and_models() {
+        let conversations = [
+            conversation(
+                "search-first",
+                &[
+                    (
+                        "questions",
+                        &[
+                            ("How do I borrow in Rust?", "Use a reference."),
+                            ("Thanks", "You're welcome."),
+                        ],
+                    ),
+                    ("more", &[("rust lifetimes", "They bound BORROWS.")]),
+                ],
+            ),
+            conversation(
+                "search-second",
+                &[("other", &[("Borrowing money", "Ask a bank.")])],
+            ),
+        ];
+        let index = SearchIndex::build(&conversations);
+        assert_eq!(index.len(), 4);
+
+        let mut hits = index.search("BORROW");
+        assert_eq!(hits.len(), 3);
+        hits.sort_by_key(|hit| (hit.location.conversation, hit.location.session));
+        let locations: Vec<(usize, usize, usize)> = hits
+            .iter()
+            .map(|hit| {
+                (
+                    hit.location.conversation,
+                    hit.location.session,
+                    hit.location.chunk,
+                )
+            })
+            .collect();
+        assert_eq!(locations, [(0, 0, 0), (0, 1, 0), (1, 0, 0)]);
+        assert_eq!(hits[0].model, "search-first.gguf");
+        assert_eq!(hits[1].session, "more");
+        assert_eq!(hits[2].model, "search-second.gguf");
+
+        // every word has to be found
+        let hits = index.search("rust borrow");
+        assert_eq!(hits.len(), 2);
+        assert!(index.search("rust money").is_empty());
+        assert!(index.search("  ").is_empty());
+    }
+
+    #[test]
+    fn points_at_the_matches() {
+        let conversations = [conversation(
+            "search-matches",
+            &[(
+                "session",
+                &[
+                    ("How do I borrow in Rust?", "Use a reference."),
+                    (
+                        &"filler ".repeat(20),
+                        "The Borrow checker, borrowing rules.",
+                    ),
+                ],
+            )],
+        )];
+        let index = SearchIndex::build(&conversations);
+
+        let hits = index.search("borrow rust");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0].snippet,
+            "How do I borrow in Rust? → Use a reference."
+        );
+        assert_eq!(hits[0].matches, [(9, 15), (19, 23)]);
+
+        let hits = index.search("borrow checker");
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].snippet.starts_with('…'));
+        assert_eq!(matched(&hits[0]), ["Borrow", "checker", "borrow"]);
+    }
+}
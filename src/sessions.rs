@@ -0,0 +1,127 @@
+use crate::{configs::Persona, conversation::ConversationChunk, paths};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// one conversation with a model, stored in [DATA DIRECTORY]/sessions/[MODEL FILE]/[ID].json
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    // name of the file the session is stored in, without its extension
+    #[serde(skip)]
+    pub id: String,
+
+    pub name: String,
+
+    // when the session was created and last saved, in seconds since the unix epoch
+    pub created: u64,
+    #[serde(default)]
+    pub updated: u64,
+
+    // replaces the model's system prompt in this session
+    #[serde(default)]
+    pub persona: Option<Persona>,
+
+    // written for this session alone, used over the persona's and the model's system prompt
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+
+    pub chunks: Vec<ConversationChunk>,
+
+    // every input sent, oldest first, including those of deleted exchanges
+    #[serde(default)]
+    pub history: Vec<String>,
+}
+
+impl Session {
+    pub fn new(model: &Path, name: String) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        // ids are creation times in milliseconds, bumped until no stored session uses them
+        let mut id = now.as_millis();
+        while Self::filepath(model, &id.to_string()).exists() {
+            id += 1;
+        }
+        Self {
+            id: id.to_string(),
+            name,
+            created: now.as_secs(),
+            updated: now.as_secs(),
+            persona: None,
+            system_prompt: None,
+            chunks: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+    /// the directory holding the sessions of a model, named after the model's file
+    pub fn directory(model: &Path) -> PathBuf {
+        paths::get()
+            .data
+            .join("sessions")
+            .join(model.file_name().unwrap_or("?".as_ref()))
+    }
+    fn filepath(model: &Path, id: &str) -> PathBuf {
+        Self::directory(model).join(format!("{}.json", id))
+    }
+    /// every session stored for the model, oldest first, unreadable files are left out
+    pub fn load_all(model: &Path) -> Vec<Self> {
+        let mut sessions: Vec<Self> = match fs::read_dir(Self::directory(model)) {
+            Ok(entries) => entries
+                .flatten()
+                .filter_map(|entry| Self::from_file(&entry.path()))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        sessions.sort_by(|a, b| a.created.cmp(&b.created).then(a.id.cmp(&b.id)));
+        sessions
+    }
+    fn from_file(filepath: &Path) -> Option<Self> {
+        if filepath
+            .extension()
+            .is_none_or(|extension| extension != "json")
+        {
+            return None;
+        }
+        let mut file = fs::OpenOptions::new().read(true).open(filepath).ok()?;
+        let mut buffer: Vec<u8> = Vec::new();
+        file.read_to_end(&mut buffer).ok()?;
+        let mut session = serde_json::from_slice::<Self>(&buffer).ok()?;
+        session.id = filepath.file_stem()?.to_string_lossy().to_string();
+        // sessions saved before inputs were recorded recall those of their exchanges
+        if session.history.is_empty() {
+            session.history = session.history_from_chunks();
+        }
+        Some(session)
+    }
+    /// the inputs of the exchanges, for sessions with no recorded history
+    pub fn history_from_chunks(&self) -> Vec<String> {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.get_input(false).to_string())
+            .collect()
+    }
+    pub fn save(&mut self, model: &Path) -> io::Result<()> {
+        self.updated = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        fs::create_dir_all(Self::directory(model))?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(Self::filepath(model, &self.id))?;
+        file.write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())?;
+        Ok(())
+    }
+    pub fn delete(&self, model: &Path) -> io::Result<()> {
+        match fs::remove_file(Self::filepath(model, &self.id)) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
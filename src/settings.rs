@@ -0,0 +1,242 @@
+use crate::{configs::Backend, templates::TemplatePreset};
+use serde_json::Value;
+
+/// how a setting is edited in the settings tab
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldKind {
+    // whole numbers within bounds, left and right move them by step
+    Integer { min: i64, max: i64, step: i64 },
+    Decimal { min: f64, max: f64, step: f64 },
+    // left, right and enter flip it
+    Toggle,
+    // typed in after pressing enter
+    Text,
+    // left and right cycle through the values
+    Choice(&'static [&'static str]),
+}
+
+/// a setting as shown in the settings tab, the key is the field's name in the config file
+pub struct Field {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub kind: FieldKind,
+}
+
+const fn field(key: &'static str, label: &'static str, kind: FieldKind) -> Field {
+    Field { key, label, kind }
+}
+
+pub const APP_FIELDS: [Field; 3] = [
+    field(
+        "timeout",
+        "generation timeout",
+        FieldKind::Decimal {
+            min: 1.0,
+            max: 86400.0,
+            step: 30.0,
+        },
+    ),
+    field(
+        "tick_rate",
+        "tick rate",
+        FieldKind::Integer {
+            min: 10,
+            max: 2000,
+            step: 10,
+        },
+    ),
+    field(
+        "startup_freeze",
+        "startup freeze",
+        FieldKind::Integer {
+            min: 0,
+            max: 10000,
+            step: 250,
+        },
+    ),
+];
+
+pub const MODEL_FIELDS: [Field; 18] = [
+    field("backend", "backend", FieldKind::Choice(&Backend::NAMES)),
+    field("server_url", "server url", FieldKind::Text),
+    field("remote_model", "remote model", FieldKind::Text),
+    field("api_key", "api key", FieldKind::Text),
+    field(
+        "tokens_to_predict",
+        "tokens to predict",
+        FieldKind::Integer {
+            min: -1,
+            max: i32::MAX as i64,
+            step: 1,
+        },
+    ),
+    field(
+        "threads_used",
+        "threads used",
+        FieldKind::Integer {
+            min: 1,
+            max: u8::MAX as i64,
+            step: 1,
+        },
+    ),
+    field(
+        "layers_offloaded_to_gpu",
+        "layers offloaded to gpu",
+        FieldKind::Integer {
+            min: 0,
+            max: u8::MAX as i64,
+            step: 1,
+        },
+    ),
+    field(
+        "prompt_context_size",
+        "prompt context size",
+        FieldKind::Integer {
+            min: 0,
+            max: u16::MAX as i64,
+            step: 256,
+        },
+    ),
+    field(
+        "randomness",
+        "randomness",
+        FieldKind::Decimal {
+            min: 0.0,
+            max: 2.0,
+            step: 0.05,
+        },
+    ),
+    field(
+        "repeat_penalty",
+        "repeat penalty",
+        FieldKind::Decimal {
+            min: 0.0,
+            max: 2.0,
+            step: 0.05,
+        },
+    ),
+    field(
+        "template",
+        "chat template",
+        FieldKind::Choice(&TemplatePreset::NAMES),
+    ),
+    field("system_prompt", "system prompt", FieldKind::Text),
+    field("prompt_prefix", "prompt prefix", FieldKind::Text),
+    field("prompt_suffix", "prompt suffix", FieldKind::Text),
+    field("ps_displayed", "prefix/suffix displayed", FieldKind::Toggle),
+    field(
+        "history_depth",
+        "history depth",
+        FieldKind::Integer {
+            min: 0,
+            max: 100,
+            step: 1,
+        },
+    ),
+    field(
+        "seed",
+        "seed",
+        FieldKind::Integer {
+            min: -1,
+            max: u32::MAX as i64,
+            step: 1,
+        },
+    ),
+    field("other", "other arguments", FieldKind::Text),
+];
+
+impl Field {
+    /// the value as shown in the settings tab, keys are never shown in full
+    pub fn display(&self, value: &Value) -> String {
+        let text = match value {
+            Value::String(text) => text.clone(),
+            value => value.to_string(),
+        };
+        if self.key == "api_key" {
+            "*".repeat(text.len().min(8))
+        } else {
+            text
+        }
+    }
+    /// what the value is edited from once enter is pressed
+    pub fn input(&self, value: &Value) -> String {
+        match value {
+            Value::String(text) => text.clone(),
+            value => value.to_string(),
+        }
+    }
+    /// the value after pressing left or right, none if the field can only be typed in
+    pub fn step(&self, value: &Value, forward: bool) -> Option<Value> {
+        match self.kind {
+            FieldKind::Integer { min, max, step } => {
+                let current = value.as_i64().unwrap_or(min);
+                let next = if forward {
+                    current.saturating_add(step)
+                } else {
+                    current.saturating_sub(step)
+                };
+                Some(next.clamp(min, max).into())
+            }
+            FieldKind::Decimal { min, max, step } => {
+                let current = value.as_f64().unwrap_or(min);
+                let next = if forward {
+                    current + step
+                } else {
+                    current - step
+                };
+                // keeps the value on the step's grid, i.e. 0.8 rather than 0.7999999999999999
+                let next = ((next / step).round() * step * 1e6).round() / 1e6;
+                Some(next.clamp(min, max).into())
+            }
+            FieldKind::Toggle => Some((!value.as_bool().unwrap_or(false)).into()),
+            FieldKind::Text => None,
+            FieldKind::Choice(choices) => {
+                let current = choices
+                    .iter()
+                    .position(|choice| Some(*choice) == value.as_str())
+                    .unwrap_or(0);
+                let next = if forward {
+                    (current + 1) % choices.len()
+                } else {
+                    (current + choices.len() - 1) % choices.len()
+                };
+                Some(choices[next].into())
+            }
+        }
+    }
+    /// reads what was typed in, explains why it isn't a valid value otherwise
+    pub fn parse(&self, input: &str) -> Result<Value, String> {
+        match self.kind {
+            FieldKind::Integer { min, max, .. } => match input.trim().parse::<i64>() {
+                Ok(number) if (min..=max).contains(&number) => Ok(number.into()),
+                _ => Err(format!(
+                    "{} must be a whole number from {} to {}",
+                    self.label, min, max
+                )),
+            },
+            FieldKind::Decimal { min, max, .. } => match input.trim().parse::<f64>() {
+                Ok(number) if (min..=max).contains(&number) => Ok(number.into()),
+                _ => Err(format!(
+                    "{} must be a number from {} to {}",
+                    self.label, min, max
+                )),
+            },
+            FieldKind::Toggle => match input.trim() {
+                "true" => Ok(true.into()),
+                "false" => Ok(false.into()),
+                _ => Err(format!("{} must be true or false", self.label)),
+            },
+            FieldKind::Text => Ok(input.into()),
+            FieldKind::Choice(choices) => {
+                match choices.iter().find(|choice| **choice == input.trim()) {
+                    Some(choice) => Ok((*choice).into()),
+                    None => Err(format!(
+                        "{} must be one of {}",
+                        self.label,
+                        choices.join(", ")
+                    )),
+                }
+            }
+        }
+    }
+}
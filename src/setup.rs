@@ -1,169 +1,535 @@
-use crate::configs::{AppConfig, ModelConfig};
+use crate::{
+    configs::{backup, AppConfig, Backend, LoadError, Loaded, ModelConfig, Persona, Versioned},
+    layers::{ConfigLayers, Rule},
+    paths,
+    sessions::Session,
+};
 use crossterm::style::Stylize;
+use serde_json::{Map, Value};
 use std::{
-    fs::{create_dir, read_dir},
-    path::PathBuf,
+    fs::{create_dir_all, read_dir},
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
-/// returns a vector containing the relative paths of the models found in ./models
-pub fn load_gguf_models_with_config(default_config: &ModelConfig) -> Vec<(PathBuf, ModelConfig)> {
-    let mut gguf_models_with_config: Vec<(PathBuf, ModelConfig)> = Vec::new();
+// whether setup messages are left out, i.e. when answering a prompt from a script where only the reply belongs on stdout
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+// whether the files found are only read, i.e. when answering a prompt from a script, which mustn't change anything on disk
+// outdated or missing config files are then left as they are and older conversations aren't moved into sessions
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+pub fn set_read_only(read_only: bool) {
+    READ_ONLY.store(read_only, Ordering::Relaxed);
+}
+
+fn read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+macro_rules! report {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*)
+        }
+    };
+}
 
-    let models_path: PathBuf = PathBuf::from("./models");
+/// returns a vector containing the paths of the models found in the models directory
+/// alongside gguf models, '.openai' files stand for models served by an OpenAI-compatible API
+/// each model's config is layered over the defaults and the rules matching its file name
+/// fails when the models directory can't be read or holds no model, there is nothing to run then
+pub fn load_gguf_models_with_config(
+    defaults: &Map<String, Value>,
+    rules: &[Rule],
+) -> Result<Vec<(PathBuf, ConfigLayers)>, String> {
+    let mut gguf_models_with_config: Vec<(PathBuf, ConfigLayers)> = Vec::new();
+
+    let models_path: &Path = &paths::get().models;
     if models_path.is_dir() {
         match read_dir(models_path) {
             Ok(element) => {
                 for entry in element.flatten() {
                     let entry = entry.path();
-                    if entry
-                        .to_str()
-                        .is_some_and(|string| string.ends_with(".bin") | string.ends_with(".gguf"))
-                    {
-                        println!(
-                            "         Found \"{}\".",
-                            entry
-                                .file_name()
-                                .unwrap_or("?".as_ref())
-                                .to_str()
-                                .unwrap_or("?")
-                                .cyan()
-                        );
-                        let mut entry_config = PathBuf::from("./configs");
-                        let mut entry_clone = entry.clone();
-                        entry_clone.set_extension("conf");
-                        entry_config.extend([entry_clone.file_name().unwrap_or("?".as_ref())]);
-                        drop(entry_clone);
+                    if entry.to_str().is_some_and(|string| {
+                        string.ends_with(".bin")
+                            | string.ends_with(".gguf")
+                            | string.ends_with(".openai")
+                    }) {
+                        let file_name = entry
+                            .file_name()
+                            .unwrap_or("?".as_ref())
+                            .to_str()
+                            .unwrap_or("?")
+                            .to_string();
+                        report!("         Found \"{}\".", file_name.as_str().cyan());
+                        let entry_config = ModelConfig::filepath(&entry);
                         match ModelConfig::from_file(&entry_config) {
-                            Some(config) => {
-                                println!("         -> linked with the associated config file");
-                                gguf_models_with_config.push((entry, config));
-                            }
-                            None => match default_config.save(&entry_config) {
-                                Ok(()) => {
-                                    println!("         -> created and saved a new associated default config file");
-                                    gguf_models_with_config.push((entry, default_config.clone()));
+                            Ok(mut loaded) => {
+                                report!("         -> linked with the associated config file");
+                                let moved = if read_only() {
+                                    Some(false)
+                                } else {
+                                    migrate_past_chunks(&entry, &mut loaded.config)
+                                };
+                                let mut layers =
+                                    ConfigLayers::new(&file_name, defaults, rules, &loaded.fields);
+                                // older releases wrote full copies of the defaults, only what differs is kept
+                                if loaded.version < 2 {
+                                    layers.prune();
                                 }
-                                Err(error) => {
-                                    println!("         -> created but did not save a new associated default config file, {}", error);
-                                    gguf_models_with_config.push((entry, default_config.clone()));
+                                if moved.is_some() {
+                                    update_config_file(
+                                        &entry_config,
+                                        &loaded,
+                                        moved == Some(true),
+                                        |_| layers.save(&entry_config),
+                                    );
                                 }
-                            },
+                                gguf_models_with_config.push((entry, layers));
+                            }
+                            Err(error) => {
+                                let layers =
+                                    ConfigLayers::new(&file_name, defaults, rules, &Map::new());
+                                report_load_error(&entry_config, error, || {
+                                    layers.save(&entry_config)
+                                });
+                                gguf_models_with_config.push((entry, layers));
+                            }
                         }
                     }
                 }
             }
             Err(error) => {
-                panic!(
-                    "[{}] Failed to read the /models directory. => {}",
-                    "FAILED".red(),
+                return Err(format!(
+                    "Failed to read the {} directory. => {}",
+                    models_path.display(),
                     error
-                );
+                ));
             }
         }
+    } else if read_only() {
+        return Err(format!(
+            "Failed to find the {} directory.",
+            models_path.display()
+        ));
     } else {
-        match create_dir("./models") {
+        match create_dir_all(models_path) {
             Ok(()) => (),
             Err(error) => {
-                panic!(
-                    "[{}] Failed to find and create the /models directory. => {}",
-                    "FAILED".red(),
+                return Err(format!(
+                    "Failed to find and create the {} directory. => {}",
+                    models_path.display(),
                     error
-                );
+                ));
             }
         }
     }
     if gguf_models_with_config.is_empty() {
-        panic!("[{}] Failed to find a single GGUF model.", "FAILED".red())
-    } else {
-        println!(
-            "[  {}  ] Loaded GGUF models with their configurations.",
-            "OK".green()
+        return Err(format!(
+            "Failed to find a single GGUF model in {}.",
+            models_path.display()
+        ));
+    }
+    report!(
+        "[  {}  ] Loaded GGUF models with their configurations.",
+        "OK".green()
+    );
+    Ok(gguf_models_with_config)
+}
+
+/// moves the conversation older releases kept in a model's config file into a session of its own
+/// returns whether there was one to move, none if moving it failed and the config file must be left as is
+fn migrate_past_chunks(model: &Path, config: &mut ModelConfig) -> Option<bool> {
+    let chunks = config.take_past_chunks();
+    if chunks.is_empty() {
+        return Some(false);
+    }
+    let mut session = Session::new(model, String::from("previous conversation"));
+    session.chunks = chunks;
+    let directory = Session::directory(model);
+    match session.save(model) {
+        Ok(()) => {
+            report!(
+                "         -> moved its conversation to {}",
+                directory.display()
+            );
+            Some(true)
+        }
+        Err(error) => {
+            report!(
+                "         -> failed to move its conversation to {}, {}",
+                directory.display(),
+                error
+            );
+            None
+        }
+    }
+}
+
+/// rewrites a config file that was migrated, held invalid values or otherwise changed while loading
+/// the original is copied beforehand and left as is if that fails
+fn update_config_file<T: Versioned>(
+    filepath: &Path,
+    loaded: &Loaded<T>,
+    changed: bool,
+    save: impl FnOnce(&T) -> io::Result<()>,
+) {
+    if loaded.newer() {
+        report!(
+            "         -> {} was written by a newer release of Sulmo (version {}), it will not be modified",
+            filepath.display(),
+            loaded.version
         );
     }
-    gguf_models_with_config
+    if !loaded.dropped.is_empty() {
+        let (noun, pronoun) = if loaded.dropped.len() == 1 {
+            ("value", "its default is")
+        } else {
+            ("values", "their defaults are")
+        };
+        report!(
+            "         -> invalid {} for {} in {}, {} used instead",
+            noun,
+            loaded.dropped.join(", ").yellow(),
+            filepath.display(),
+            pronoun
+        );
+    }
+    if loaded.newer() || !(changed || loaded.outdated()) || read_only() {
+        return;
+    }
+    let backup = match backup(filepath) {
+        Ok(backup) => backup,
+        Err(error) => {
+            report!(
+                "         -> failed to copy {} before updating it, it was left as is, {}",
+                filepath.display(),
+                error
+            );
+            return;
+        }
+    };
+    let action = if loaded.version < T::VERSION {
+        format!(
+            "updated {} from version {} to {}",
+            filepath.display(),
+            loaded.version,
+            T::VERSION
+        )
+    } else {
+        format!("updated {}", filepath.display())
+    };
+    match save(&loaded.config) {
+        Ok(()) => report!(
+            "         -> {}, the original was kept as {}",
+            action,
+            backup.display()
+        ),
+        Err(error) => report!(
+            "         -> failed to save {} once {}, {}",
+            filepath.display(),
+            action.replacen("updated", "updating", 1),
+            error
+        ),
+    }
+}
+
+/// explains why a config file couldn't be used, replaces it with the defaults unless it could hold anything worth keeping
+fn report_load_error(
+    filepath: &Path,
+    error: LoadError,
+    save_default: impl FnOnce() -> io::Result<()>,
+) {
+    if read_only() {
+        return;
+    }
+    let reason = match error {
+        LoadError::Missing => String::from("it was missing"),
+        LoadError::Unreadable(error) => {
+            report!(
+                "         -> failed to read {}, the defaults are used without replacing it, {}",
+                filepath.display(),
+                error
+            );
+            return;
+        }
+        LoadError::Broken(error) => match backup(filepath) {
+            Ok(backup) => format!(
+                "it was invalid ({}) and was kept as {}",
+                error,
+                backup.display()
+            ),
+            Err(backup_error) => {
+                report!(
+                    "         -> {} is invalid ({}) and could not be copied ({}), the defaults are used without replacing it",
+                    filepath.display(),
+                    error,
+                    backup_error
+                );
+                return;
+            }
+        },
+    };
+    match save_default() {
+        Ok(()) => report!(
+            "         -> created and saved a new default {}, {}",
+            filepath.display(),
+            reason
+        ),
+        Err(error) => report!(
+            "         -> created but did not save a new default {}, {}, {}",
+            filepath.display(),
+            reason,
+            error
+        ),
+    }
 }
 
-pub fn load_default_llama_configuration() -> ModelConfig {
-    let configuration = match ModelConfig::default_from_file() {
-        Some(config) => config,
-        None => {
-            println!("         Failed to load the default llama configuration. Generating new configuration...");
+/// the fields of ./configs/model.conf, the defaults every model's config is layered over
+pub fn load_default_llama_configuration() -> Map<String, Value> {
+    let filepath = &ModelConfig::default_filepath();
+    let fields = match ModelConfig::default_from_file() {
+        Ok(loaded) => {
+            update_config_file(filepath, &loaded, false, |config| config.save(filepath));
+            loaded.fields
+        }
+        Err(error) => {
             let configuration = ModelConfig::default();
-            match configuration.save(ModelConfig::DEFAULT_FILEPATH) {
-                Ok(()) => println!(
-                    "[  {}  ] Created and saved a default llama configuration.",
-                    "OK".green()
-                ),
-                Err(error) => println!(
-                    "[ {} ] Created but failed to save a default llama configuration. => {}",
-                    "!!!!".yellow(),
-                    error
-                ),
+            report_load_error(filepath, error, || configuration.save(filepath));
+            match serde_json::to_value(configuration) {
+                Ok(Value::Object(fields)) => fields,
+                _ => Map::new(),
             }
-            configuration
         }
     };
-    println!("[  {}  ] Loaded default llama configuration.", "OK".green());
-    configuration
+    report!("[  {}  ] Loaded default llama configuration.", "OK".green());
+    fields
+}
+
+pub fn load_rules() -> Vec<Rule> {
+    let rules = match Rule::library_from_file() {
+        Ok(rules) => rules,
+        Err(error) => {
+            let rules = Rule::defaults();
+            report_load_error(&Rule::filepath(), error, || Rule::save_library(&rules));
+            rules
+        }
+    };
+    report!(
+        "[  {}  ] Loaded {} config rule{}.",
+        "OK".green(),
+        rules.len(),
+        if rules.len() == 1 { "" } else { "s" }
+    );
+    rules
 }
 
 pub fn load_app_configuration() -> AppConfig {
-    let configs_path: PathBuf = PathBuf::from("./configs");
-    if !configs_path.is_dir() {
-        println!("         Failed to find the ./configs directory. Attempting to create it...");
-        match std::fs::create_dir(configs_path) {
+    let configs_path: &Path = &paths::get().configs;
+    if !configs_path.is_dir() && !read_only() {
+        report!(
+            "         Failed to find the {} directory. Attempting to create it...",
+            configs_path.display()
+        );
+        match create_dir_all(configs_path) {
             Ok(()) => (),
             Err(error) => {
-                println!(
-                    "         Failed to create the ./config directory. => {}",
+                report!(
+                    "         Failed to create the {} directory. => {}",
+                    configs_path.display(),
                     error
                 );
             }
         }
     }
 
+    let filepath = &AppConfig::filepath();
     let configuration = match AppConfig::from_file() {
-        Some(config) => config,
-        None => {
-            println!(
-                "         Failed to load sulmo configuration. Generating a new configuration..."
-            );
+        Ok(loaded) => {
+            update_config_file(filepath, &loaded, false, |config| config.save());
+            loaded.config
+        }
+        Err(error) => {
             let configuration = AppConfig::default();
-            match configuration.save() {
-                Ok(()) => println!(
-                    "[  {}  ] Created and saved sulmo configuration.",
-                    "OK".green()
-                ),
-                Err(error) => println!(
-                    "[ {} ] Created but failed to save sulmo configuration. => {}",
-                    "!!!!".yellow(),
-                    error
-                ),
-            }
+            report_load_error(filepath, error, || configuration.save());
             configuration
         }
     };
-    println!("[  {}  ] Loaded sulmo configuration.", "OK".green());
+    report!("[  {}  ] Loaded sulmo configuration.", "OK".green());
     configuration
 }
 
-pub fn check_llama_cpp() {
-    let normal_path: PathBuf = PathBuf::from("./llama-cpp/main");
-    let alt_path: PathBuf = PathBuf::from("./llama.cpp/main");
+pub fn load_personas() -> Vec<Persona> {
+    let personas = match Persona::library_from_file() {
+        Ok(personas) => personas,
+        Err(error) => {
+            let personas = Persona::defaults();
+            report_load_error(&Persona::filepath(), error, || {
+                Persona::save_library(&personas)
+            });
+            personas
+        }
+    };
+    report!("[  {}  ] Loaded {} personas.", "OK".green(), personas.len());
+    personas
+}
+
+/// looks for llama.cpp if any model runs on it, either through a process per prompt or a server started by Sulmo
+/// a missing installation is only warned about, those models fail to generate until it's there
+pub fn check_llama_cpp(models: &[(PathBuf, ConfigLayers)]) {
+    let needed = models.iter().any(|(_, layers)| {
+        let config = layers.resolve();
+        match config.backend {
+            Backend::LlamaCpp => true,
+            Backend::LlamaServer => config.server_url.is_empty(),
+            Backend::OpenAi => false,
+        }
+    });
+    if !needed {
+        report!(
+            "[  {}  ] No model runs on a local llama.cpp, it isn't needed.",
+            "OK".green()
+        );
+        return;
+    }
+    let normal_path: &Path = &paths::get().llama_bin;
+    // llama.cpp's own folder name is accepted in place of ./llama-cpp
+    let normal_folder = normal_path.parent().unwrap_or(Path::new("."));
+    let alt_folder = normal_folder.with_file_name("llama.cpp");
+    let alt_path: PathBuf = alt_folder.join(normal_path.file_name().unwrap_or("main".as_ref()));
 
     if normal_path.is_file() {
-        println!("[  {}  ] Found ./llama-cpp/main", "OK".green());
-    } else if alt_path.is_file() {
-        match std::fs::rename("./llama.cpp", "./llama-cpp") {
-            Ok(()) => println!(
-                "[  {}  ] Found ./llama.cpp/main and renamed ./llama.cpp to ./llama-cpp",
-                "OK".green()
+        report!("[  {}  ] Found {}", "OK".green(), normal_path.display());
+    } else if normal_folder.ends_with("llama-cpp") && alt_path.is_file() {
+        match std::fs::rename(&alt_folder, normal_folder) {
+            Ok(()) => report!(
+                "[  {}  ] Found {} and renamed {} to {}",
+                "OK".green(),
+                alt_path.display(),
+                alt_folder.display(),
+                normal_folder.display()
             ),
             Err(error) => {
-                panic!("[{}] Found ./llama.cpp/main but failed to rename the folder to ./llama-cpp. => {}", "FAILED".red(), error);
+                report!(
+                    "[ {} ] Found {} but failed to rename the folder to {}. => {}",
+                    "!!!!".yellow(),
+                    alt_path.display(),
+                    normal_folder.display(),
+                    error
+                );
             }
         }
     } else {
-        panic!("[{}] Failed to find ./llama-cpp/main or ./llama.cpp/main, please carefully read the README", "FAILED".red());
+        report!("[ {} ] Failed to find {}, models running on llama.cpp won't be able to reply, please carefully read the README or point to it with --llama-bin", "!!!!".yellow(), normal_path.display());
+    }
+}
+
+/// the first model whose file name contains the given name, case is ignored
+pub fn find_model(models: &[(PathBuf, ConfigLayers)], name: &str) -> Option<usize> {
+    let name = name.to_lowercase();
+    models.iter().position(|(model, _)| {
+        model
+            .file_name()
+            .is_some_and(|file_name| file_name.to_string_lossy().to_lowercase().contains(&name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{configs::load_versioned, mock};
+    use std::{cell::Cell, fs, sync::Mutex};
+
+    // read-only is set for the whole process, tests depending on it take turns
+    static READ_ONLY_TESTS: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn leaves_files_alone_when_read_only() {
+        let _turn = READ_ONLY_TESTS
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        mock::use_temporary_paths();
+        set_quiet(true);
+        set_read_only(true);
+        let paths = paths::get();
+        fs::create_dir_all(&paths.models).unwrap();
+        fs::create_dir_all(&paths.configs).unwrap();
+        let model = paths.models.join("read-only.gguf");
+        fs::write(&model, "").unwrap();
+        // outdated, with an invalid value and a conversation older releases kept in it
+        let config = r#"{"version":1,"randomness":"hot","past_chunks":[{"raw_input":"hi","input":"hi","output":"hello"}]}"#;
+        fs::write(ModelConfig::filepath(&model), config).unwrap();
+        fs::write(ModelConfig::default_filepath(), "{ broken").unwrap();
+        let listing = || {
+            let mut files: Vec<_> = fs::read_dir(&paths.configs)
+                .unwrap()
+                .flatten()
+                .map(|entry| entry.file_name())
+                .collect();
+            files.sort();
+            files
+        };
+        let before = listing();
+
+        let defaults = load_default_llama_configuration();
+        let models = load_gguf_models_with_config(&defaults, &[]).unwrap();
+        assert!(models.iter().any(|(path, _)| *path == model));
+        assert_eq!(listing(), before);
+        assert_eq!(
+            fs::read_to_string(ModelConfig::filepath(&model)).unwrap(),
+            config
+        );
+        assert_eq!(
+            fs::read_to_string(ModelConfig::default_filepath()).unwrap(),
+            "{ broken"
+        );
+        assert!(!Session::directory(&model).exists());
+        set_read_only(false);
+    }
+
+    #[test]
+    fn updates_outdated_files_after_a_backup() {
+        let _turn = READ_ONLY_TESTS
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        mock::use_temporary_paths();
+        set_quiet(true);
+        let configs = &paths::get().configs;
+        fs::create_dir_all(configs).unwrap();
+        let backups = |name: &str| {
+            fs::read_dir(configs)
+                .unwrap()
+                .flatten()
+                .filter(|entry| {
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    file_name.starts_with(&format!("{}.", name)) && file_name.ends_with(".bak")
+                })
+                .count()
+        };
+
+        let outdated = configs.join("outdated.conf");
+        fs::write(&outdated, r#"{"timeout":5.0}"#).unwrap();
+        let loaded = load_versioned::<AppConfig>(&outdated).unwrap();
+        let saved = Cell::new(false);
+        update_config_file(&outdated, &loaded, false, |_| {
+            saved.set(true);
+            Ok(())
+        });
+        assert!(saved.get());
+        assert_eq!(backups("outdated.conf"), 1);
+
+        let newer = configs.join("newer.conf");
+        fs::write(&newer, r#"{"version":99,"timeout":"soon"}"#).unwrap();
+        let loaded = load_versioned::<AppConfig>(&newer).unwrap();
+        update_config_file(&newer, &loaded, true, |_| {
+            panic!("a newer file was rewritten")
+        });
+        assert_eq!(backups("newer.conf"), 0);
     }
 }
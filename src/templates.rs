@@ -0,0 +1,303 @@
+use crate::backend::{Message, Role};
+use serde::{Deserialize, Serialize};
+
+/// the ways a conversation can be laid out for a model, selected in its configuration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TemplatePreset {
+    // the model's prompt prefix and suffix around every prompt
+    #[default]
+    Custom,
+    // the preset matching the chat template embedded in the GGUF file, custom if there is none
+    Auto,
+    #[serde(rename = "chatml")]
+    ChatMl,
+    Llama2,
+    Llama3,
+    Mistral,
+    Zephyr,
+    Vicuna,
+    Alpaca,
+    Gemma,
+}
+
+impl TemplatePreset {
+    // every preset as written in a config file
+    pub const NAMES: [&'static str; 10] = [
+        "custom", "auto", "chatml", "llama2", "llama3", "mistral", "zephyr", "vicuna", "alpaca",
+        "gemma",
+    ];
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Custom => "custom",
+            Self::Auto => "auto",
+            Self::ChatMl => "chatml",
+            Self::Llama2 => "llama2",
+            Self::Llama3 => "llama3",
+            Self::Mistral => "mistral",
+            Self::Zephyr => "zephyr",
+            Self::Vicuna => "vicuna",
+            Self::Alpaca => "alpaca",
+            Self::Gemma => "gemma",
+        }
+    }
+    /// finds the preset producing the same layout as a GGUF file's jinja chat template
+    pub fn detect(jinja: &str) -> Option<Self> {
+        if jinja.contains("<|im_start|>") {
+            Some(Self::ChatMl)
+        } else if jinja.contains("<|start_header_id|>") {
+            Some(Self::Llama3)
+        } else if jinja.contains("[INST]") && jinja.contains("<<SYS>>") {
+            Some(Self::Llama2)
+        } else if jinja.contains("[INST]") {
+            Some(Self::Mistral)
+        } else if jinja.contains("<start_of_turn>") {
+            Some(Self::Gemma)
+        } else if jinja.contains("<|user|>") {
+            Some(Self::Zephyr)
+        } else if jinja.contains("### Instruction") {
+            Some(Self::Alpaca)
+        } else if jinja.contains("USER:") && jinja.contains("ASSISTANT:") {
+            Some(Self::Vicuna)
+        } else {
+            None
+        }
+    }
+    /// the layout of a fixed preset, None for custom and auto which depend on the model
+    pub fn template(&self) -> Option<ChatTemplate> {
+        let (parts, system_in_first_user) = match self {
+            Self::Custom | Self::Auto => return None,
+            Self::ChatMl => (
+                [
+                    "<|im_start|>system\n",
+                    "<|im_end|>\n",
+                    "<|im_start|>user\n",
+                    "<|im_end|>\n",
+                    "<|im_start|>assistant\n",
+                    "<|im_end|>\n",
+                ],
+                false,
+            ),
+            Self::Llama2 => (
+                [
+                    "<<SYS>>\n",
+                    "\n<</SYS>>\n\n",
+                    "[INST] ",
+                    " [/INST]",
+                    " ",
+                    " ",
+                ],
+                true,
+            ),
+            Self::Llama3 => (
+                [
+                    "<|start_header_id|>system<|end_header_id|>\n\n",
+                    "<|eot_id|>",
+                    "<|start_header_id|>user<|end_header_id|>\n\n",
+                    "<|eot_id|>",
+                    "<|start_header_id|>assistant<|end_header_id|>\n\n",
+                    "<|eot_id|>",
+                ],
+                false,
+            ),
+            Self::Mistral => (["", "\n\n", "[INST] ", " [/INST]", "", " "], true),
+            Self::Zephyr => (
+                [
+                    "<|system|>\n",
+                    "</s>\n",
+                    "<|user|>\n",
+                    "</s>\n",
+                    "<|assistant|>\n",
+                    "</s>\n",
+                ],
+                false,
+            ),
+            Self::Vicuna => (["", "\n\n", "USER: ", "\n", "ASSISTANT: ", "\n"], false),
+            Self::Alpaca => (
+                [
+                    "",
+                    "\n\n",
+                    "### Instruction:\n",
+                    "\n\n",
+                    "### Response:\n",
+                    "\n\n",
+                ],
+                false,
+            ),
+            Self::Gemma => (
+                [
+                    "",
+                    "\n\n",
+                    "<start_of_turn>user\n",
+                    "<end_of_turn>\n",
+                    "<start_of_turn>model\n",
+                    "<end_of_turn>\n",
+                ],
+                true,
+            ),
+        };
+        Some(ChatTemplate::new(parts, system_in_first_user))
+    }
+}
+
+/// how the turns of a conversation are laid out in the text fed to a model
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatTemplate {
+    pub system_prefix: String,
+    pub system_suffix: String,
+    pub user_prefix: String,
+    pub user_suffix: String,
+    pub assistant_prefix: String,
+    pub assistant_suffix: String,
+    // models without a system role get the system prompt at the start of the first user message
+    pub system_in_first_user: bool,
+}
+
+impl ChatTemplate {
+    /// parts are the system, user and assistant prefixes and suffixes, in that order
+    pub fn new(parts: [&str; 6], system_in_first_user: bool) -> Self {
+        Self {
+            system_prefix: parts[0].to_string(),
+            system_suffix: parts[1].to_string(),
+            user_prefix: parts[2].to_string(),
+            user_suffix: parts[3].to_string(),
+            assistant_prefix: parts[4].to_string(),
+            assistant_suffix: parts[5].to_string(),
+            system_in_first_user,
+        }
+    }
+    /// lays out the messages, ending with the opening of the assistant's reply
+    pub fn render(&self, messages: &[Message]) -> String {
+        let mut text = String::new();
+        let mut pending_system: Option<&str> = None;
+        for message in messages {
+            match message.role {
+                Role::System if self.system_in_first_user => {
+                    pending_system = Some(&message.content)
+                }
+                Role::System => {
+                    text.push_str(&self.system_prefix);
+                    text.push_str(&message.content);
+                    text.push_str(&self.system_suffix);
+                }
+                Role::User => {
+                    text.push_str(&self.user_prefix);
+                    if let Some(system) = pending_system.take() {
+                        text.push_str(&self.system_prefix);
+                        text.push_str(system);
+                        text.push_str(&self.system_suffix);
+                    }
+                    text.push_str(&message.content);
+                    text.push_str(&self.user_suffix);
+                }
+                Role::Assistant => {
+                    text.push_str(&self.assistant_prefix);
+                    text.push_str(&message.content);
+                    text.push_str(&self.assistant_suffix);
+                }
+            }
+        }
+        text.push_str(&self.assistant_prefix);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, content: &str) -> Message {
+        Message {
+            role,
+            content: content.to_string(),
+        }
+    }
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            message(Role::System, "Be brief."),
+            message(Role::User, "Hi"),
+            message(Role::Assistant, "Hello!"),
+            message(Role::User, "Bye"),
+        ]
+    }
+
+    #[test]
+    fn renders_every_turn_then_opens_the_reply() {
+        let template = TemplatePreset::ChatMl.template().unwrap();
+        assert_eq!(
+            template.render(&conversation()),
+            "<|im_start|>system\nBe brief.<|im_end|>\n\
+             <|im_start|>user\nHi<|im_end|>\n\
+             <|im_start|>assistant\nHello!<|im_end|>\n\
+             <|im_start|>user\nBye<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn puts_the_system_prompt_in_the_first_user_message() {
+        let template = TemplatePreset::Llama2.template().unwrap();
+        assert_eq!(
+            template.render(&conversation()),
+            "[INST] <<SYS>>\nBe brief.\n<</SYS>>\n\nHi [/INST] Hello! [INST] Bye [/INST] "
+        );
+        let template = TemplatePreset::Gemma.template().unwrap();
+        assert_eq!(
+            template.render(&[message(Role::User, "Hi")]),
+            "<start_of_turn>user\nHi<end_of_turn>\n<start_of_turn>model\n"
+        );
+    }
+
+    #[test]
+    fn only_fixed_presets_have_a_template() {
+        assert_eq!(TemplatePreset::Custom.template(), None);
+        assert_eq!(TemplatePreset::Auto.template(), None);
+        for name in TemplatePreset::NAMES.iter().skip(2) {
+            let preset: TemplatePreset = serde_json::from_value((*name).into()).unwrap();
+            assert_eq!(preset.as_str(), *name);
+            assert!(preset.template().is_some());
+        }
+    }
+
+    #[test]
+    fn detects_presets_from_embedded_templates() {
+        let detect = TemplatePreset::detect;
+        assert_eq!(
+            detect("{% for message in messages %}{{'<|im_start|>' + message['role'] + '\\n' + message['content'] + '<|im_end|>' + '\\n'}}{% endfor %}"),
+            Some(TemplatePreset::ChatMl)
+        );
+        assert_eq!(
+            detect("{{ '<|start_header_id|>' + message['role'] + '<|end_header_id|>\\n\\n' + message['content'] | trim + '<|eot_id|>' }}"),
+            Some(TemplatePreset::Llama3)
+        );
+        assert_eq!(
+            detect("{% set content = '<<SYS>>\\n' + system_message + '\\n<</SYS>>\\n\\n' %}{{ bos_token + '[INST] ' + content + ' [/INST]' }}"),
+            Some(TemplatePreset::Llama2)
+        );
+        assert_eq!(
+            detect("{{ bos_token }}{% for message in messages %}{{ '[INST] ' + message['content'] + ' [/INST]' }}{% endfor %}"),
+            Some(TemplatePreset::Mistral)
+        );
+        assert_eq!(
+            detect("{{ '<start_of_turn>' + role + '\\n' + message['content'] | trim + '<end_of_turn>\\n' }}"),
+            Some(TemplatePreset::Gemma)
+        );
+        assert_eq!(
+            detect("{% if message['role'] == 'user' %}{{ '<|user|>\\n' + message['content'] + eos_token }}{% endif %}"),
+            Some(TemplatePreset::Zephyr)
+        );
+        assert_eq!(detect("{{ messages | tojson }}"), None);
+    }
+
+    #[test]
+    fn resolves_auto_from_the_embedded_template() {
+        let mut config = crate::configs::ModelConfig::default();
+        config.template = TemplatePreset::Auto;
+        assert_eq!(
+            config.resolve_template(Some("{{ '<|im_start|>' }}")),
+            TemplatePreset::ChatMl
+        );
+        assert_eq!(config.resolve_template(None), TemplatePreset::Custom);
+    }
+}
@@ -1,4 +1,10 @@
-use std::path::{Path, PathBuf};
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 pub fn pathbuf_to_string(pathbuf: &Path, desired_length: usize, error_str: &str) -> String {
     let filestem: &str = pathbuf
@@ -13,12 +19,189 @@ pub fn pathbuf_to_string(pathbuf: &Path, desired_length: usize, error_str: &str)
     }
 }
 
-pub fn pathbuf_helper(pathbuf: &Path, prefix: &Path, extension: &str) -> Option<PathBuf> {
-    let mut pathbuf_clone = pathbuf.to_owned();
-    if !pathbuf_clone.set_extension(extension) {
-        return None;
+/// i.e. 1700000000 => 2023-11-14 22:13 UTC
+pub fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let minutes = seconds % 86400 / 60;
+    // civil date from days since the unix epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+/// the rows a line takes up once wrapped to the width, broken after a space when possible
+/// spaces at the breaks are left out, the indentation the line starts with is kept
+pub fn wrap_line(line: Line, width: usize) -> Vec<Line> {
+    let width = width.max(1);
+    if line.width() <= width {
+        return vec![line];
+    }
+    let graphemes: Vec<(&str, Style)> = line
+        .spans
+        .iter()
+        .flat_map(|span| {
+            span.content
+                .graphemes(true)
+                .map(move |grapheme| (grapheme, span.style))
+        })
+        .collect();
+    let is_space = |grapheme: &str| grapheme.chars().all(char::is_whitespace);
+    let row = |graphemes: &[(&str, Style)]| {
+        let end = graphemes
+            .iter()
+            .rposition(|(grapheme, _)| !is_space(grapheme))
+            .map_or(0, |index| index + 1);
+        // consecutive graphemes of the same style make up one span
+        let mut spans: Vec<Span> = Vec::new();
+        for (grapheme, style) in &graphemes[..end] {
+            match spans.last_mut() {
+                Some(span) if span.style == *style => span.content.to_mut().push_str(grapheme),
+                _ => spans.push(Span::styled(grapheme.to_string(), *style)),
+            }
+        }
+        Line {
+            spans,
+            alignment: line.alignment,
+        }
     };
-    Some(PathBuf::from_iter(
-        [prefix.as_os_str(), pathbuf_clone.file_name()?].iter(),
-    ))
+    let mut rows = Vec::new();
+    let (mut start, mut index, mut row_width) = (0, 0, 0);
+    // right after the last space of the row, where it can be broken without splitting a word
+    let mut last_break: Option<usize> = None;
+    while index < graphemes.len() {
+        let grapheme = graphemes[index].0;
+        let grapheme_width = grapheme.width();
+        if row_width + grapheme_width > width && index > start {
+            // a space overflowing the row is where it breaks, the indentation is never broken after
+            let end = match last_break {
+                _ if is_space(grapheme) => index,
+                Some(end)
+                    if graphemes[start..end]
+                        .iter()
+                        .any(|(grapheme, _)| !is_space(grapheme))
+                        && graphemes[end..index]
+                            .iter()
+                            .map(|(grapheme, _)| grapheme.width())
+                            .sum::<usize>()
+                            + grapheme_width
+                            <= width =>
+                {
+                    end
+                }
+                _ => index,
+            };
+            rows.push(row(&graphemes[start..end]));
+            start = end;
+            last_break = None;
+            if start == index && is_space(grapheme) {
+                start += 1;
+                index += 1;
+                row_width = 0;
+                continue;
+            }
+            row_width = graphemes[start..index]
+                .iter()
+                .map(|(grapheme, _)| grapheme.width())
+                .sum();
+        }
+        row_width += grapheme_width;
+        if is_space(grapheme) {
+            last_break = Some(index + 1);
+        }
+        index += 1;
+    }
+    rows.push(row(&graphemes[start..]));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Color;
+
+    fn rows(line: Line, width: usize) -> Vec<String> {
+        wrap_line(line, width)
+            .iter()
+            .map(|row| row.spans.iter().map(|span| span.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn breaks_after_a_space() {
+        assert_eq!(
+            rows(Line::from("the quick brown fox"), 10),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(rows(Line::from("short"), 10), vec!["short"]);
+    }
+
+    #[test]
+    fn splits_words_longer_than_the_width() {
+        assert_eq!(
+            rows(Line::from("a abcdefghijkl b"), 5),
+            vec!["a", "abcde", "fghij", "kl b"]
+        );
+    }
+
+    #[test]
+    fn keeps_the_indentation_it_starts_with() {
+        assert_eq!(
+            rows(Line::from("    indented words here"), 12),
+            vec!["    indented", "words here"]
+        );
+    }
+
+    #[test]
+    fn measures_graphemes_by_their_width() {
+        // each ideograph takes up two columns, the flag and the accented letter one grapheme each
+        assert_eq!(rows(Line::from("日本語の"), 5), vec!["日本", "語の"]);
+        assert_eq!(
+            rows(Line::from("🇫🇷🇫🇷🇫🇷 e\u{301}e\u{301}"), 6),
+            vec!["🇫🇷🇫🇷🇫🇷", "e\u{301}e\u{301}"]
+        );
+    }
+
+    #[test]
+    fn keeps_styles_across_breaks() {
+        let red = Style::default().fg(Color::Red);
+        let blue = Style::default().fg(Color::Blue);
+        let wrapped = wrap_line(
+            Line::from(vec![
+                Span::styled("red words ", red),
+                Span::styled("blue", blue),
+            ]),
+            9,
+        );
+        assert_eq!(wrapped.len(), 2);
+        assert_eq!(wrapped[0].spans, vec![Span::styled("red words", red)]);
+        assert_eq!(wrapped[1].spans, vec![Span::styled("blue", blue)]);
+        let wrapped = wrap_line(
+            Line::from(vec![Span::styled("abc", red), Span::styled("defg", blue)]),
+            5,
+        );
+        assert_eq!(
+            wrapped[0].spans,
+            vec![Span::styled("abc", red), Span::styled("de", blue)]
+        );
+        assert_eq!(wrapped[1].spans, vec![Span::styled("fg", blue)]);
+    }
 }